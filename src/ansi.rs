@@ -0,0 +1,469 @@
+use egui::text::LayoutJob;
+use egui::{Color32, FontId, Stroke, TextFormat};
+
+/// Piece of a line of terminal output.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// Parameters of a `ESC [ ... m` sequence, e.g. `1;31`.
+    Sgr(&'a str),
+}
+
+/// Splits a line into text and SGR sequences, other escape sequences are dropped.
+pub fn segments(line: &str) -> Segments<'_> {
+    Segments { rest: line }
+}
+
+/// The line as plain text, without any escape sequences.
+pub fn strip(line: &str) -> String {
+    segments(line)
+        .filter_map(|s| match s {
+            Segment::Text(text) => Some(text),
+            Segment::Sgr(_) => None,
+        })
+        .collect()
+}
+
+pub struct Segments<'a> {
+    rest: &'a str,
+}
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Segment<'a>> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let Some(rest) = self.rest.strip_prefix('\x1b') else {
+                let end = self.rest.find('\x1b').unwrap_or(self.rest.len());
+                let (text, rest) = self.rest.split_at(end);
+                self.rest = rest;
+                return Some(Segment::Text(text));
+            };
+
+            if let Some(csi) = rest.strip_prefix('[') {
+                // Parameter and intermediate bytes, then a single final byte.
+                let Some(end) = csi.find(|c: char| ('\x40'..='\x7e').contains(&c)) else {
+                    self.rest = "";
+                    return None;
+                };
+                let params = &csi[..end];
+                let is_sgr = csi[end..].starts_with('m');
+                self.rest = &csi[end + 1..];
+                if is_sgr {
+                    return Some(Segment::Sgr(params));
+                }
+            } else if let Some(osc) = rest.strip_prefix(']') {
+                // Terminated by BEL or ST (ESC \).
+                let end = osc.find(['\x07', '\x1b']).unwrap_or(osc.len());
+                let terminator = osc[end..]
+                    .strip_prefix('\x07')
+                    .or_else(|| osc[end..].strip_prefix("\x1b\\"));
+                self.rest = terminator.unwrap_or(&osc[end..]);
+            } else {
+                // Other escapes are intermediate bytes followed by a single final byte.
+                let start = rest.trim_start_matches(|c: char| ('\x20'..='\x2f').contains(&c));
+                let mut chars = start.chars();
+                chars.next();
+                self.rest = chars.as_str();
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnsiColor {
+    /// One of the 16 standard colors, 8-15 are the bright variants.
+    Named(u8),
+    /// A color from the 256 color palette.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+impl AnsiColor {
+    const PALETTE: [Color32; 16] = [
+        Color32::from_rgb(0, 0, 0),
+        Color32::from_rgb(205, 49, 49),
+        Color32::from_rgb(13, 188, 121),
+        Color32::from_rgb(229, 229, 16),
+        Color32::from_rgb(36, 114, 200),
+        Color32::from_rgb(188, 63, 188),
+        Color32::from_rgb(17, 168, 205),
+        Color32::from_rgb(229, 229, 229),
+        Color32::from_rgb(102, 102, 102),
+        Color32::from_rgb(241, 76, 76),
+        Color32::from_rgb(35, 209, 139),
+        Color32::from_rgb(245, 245, 67),
+        Color32::from_rgb(59, 142, 234),
+        Color32::from_rgb(214, 112, 214),
+        Color32::from_rgb(41, 184, 219),
+        Color32::from_rgb(255, 255, 255),
+    ];
+
+    pub fn to_color32(self) -> Color32 {
+        match self {
+            AnsiColor::Named(i) => AnsiColor::PALETTE[i as usize & 15],
+            AnsiColor::Indexed(i @ 0..=15) => AnsiColor::PALETTE[i as usize],
+            AnsiColor::Indexed(i @ 16..=231) => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let i = i - 16;
+                Color32::from_rgb(level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            AnsiColor::Indexed(i) => {
+                let gray = 8 + (i - 232) * 10;
+                Color32::from_rgb(gray, gray, gray)
+            }
+            AnsiColor::Rgb(r, g, b) => Color32::from_rgb(r, g, b),
+        }
+    }
+}
+
+/// Graphics state set by SGR sequences, carried from one sequence (and line) to the next.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct AnsiStyle {
+    pub foreground: Option<AnsiColor>,
+    pub background: Option<AnsiColor>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub inverse: bool,
+}
+impl AnsiStyle {
+    pub const DEFAULT_FOREGROUND: Color32 = Color32::GRAY;
+    pub const DEFAULT_BACKGROUND: Color32 = Color32::from_rgb(27, 27, 27);
+
+    /// Applies the parameters of one SGR sequence, an empty list resets like `0` does.
+    pub fn apply_sgr(&mut self, params: &str) {
+        let mut params = params
+            .split(';')
+            .map(|p| {
+                let mut sub = p.split(':').map(|s| s.parse::<u32>().ok());
+                (sub.next().flatten().unwrap_or(0), sub.collect::<Vec<_>>())
+            })
+            .peekable();
+
+        while let Some((code, sub)) = params.next() {
+            match code {
+                0 => *self = AnsiStyle::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                9 => self.strikethrough = true,
+                21 | 22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                29 => self.strikethrough = false,
+                30..=37 => self.foreground = Some(AnsiColor::Named((code - 30) as u8)),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(AnsiColor::Named((code - 40) as u8)),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(AnsiColor::Named((code - 90 + 8) as u8)),
+                100..=107 => self.background = Some(AnsiColor::Named((code - 100 + 8) as u8)),
+                38 | 48 => {
+                    // Either `38;5;n` / `38;2;r;g;b` or the colon form `38:5:n` / `38:2::r:g:b`.
+                    let color = if sub.is_empty() {
+                        let mut next = || params.next().map(|(code, _)| code);
+                        match next() {
+                            Some(5) => next().map(|i| AnsiColor::Indexed(i as u8)),
+                            Some(2) => match (next(), next(), next()) {
+                                (Some(r), Some(g), Some(b)) => {
+                                    Some(AnsiColor::Rgb(r as u8, g as u8, b as u8))
+                                }
+                                _ => None,
+                            },
+                            _ => None,
+                        }
+                    } else {
+                        match sub.as_slice() {
+                            [Some(5), Some(i)] => Some(AnsiColor::Indexed(*i as u8)),
+                            [Some(2), _, Some(r), Some(g), Some(b)]
+                            | [Some(2), Some(r), Some(g), Some(b)] => {
+                                Some(AnsiColor::Rgb(*r as u8, *g as u8, *b as u8))
+                            }
+                            _ => None,
+                        }
+                    };
+                    if code == 38 {
+                        self.foreground = color.or(self.foreground);
+                    } else {
+                        self.background = color.or(self.background);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn text_format(&self, font_id: FontId) -> TextFormat {
+        let foreground = match self.foreground {
+            // Terminals traditionally show bold text in the bright variant of its color.
+            Some(AnsiColor::Named(i)) if self.bold && i < 8 => AnsiColor::Named(i + 8).to_color32(),
+            Some(color) => color.to_color32(),
+            None if self.bold => Color32::WHITE,
+            None => AnsiStyle::DEFAULT_FOREGROUND,
+        };
+        let background = self.background.map(AnsiColor::to_color32);
+
+        let (mut color, background) = if self.inverse {
+            (
+                background.unwrap_or(AnsiStyle::DEFAULT_BACKGROUND),
+                foreground,
+            )
+        } else {
+            (foreground, background.unwrap_or(Color32::TRANSPARENT))
+        };
+        if self.dim {
+            color = color.linear_multiply(0.6);
+        }
+        let line = |enabled| {
+            if enabled {
+                Stroke::new(1.0, color)
+            } else {
+                Stroke::NONE
+            }
+        };
+
+        TextFormat {
+            font_id,
+            color,
+            background,
+            italics: self.italic,
+            underline: line(self.underline),
+            strikethrough: line(self.strikethrough),
+            ..Default::default()
+        }
+    }
+}
+
+/// Lays out one line of terminal output, updating `style` with any SGR sequences in it.
+pub fn layout_line(line: &str, style: &mut AnsiStyle, font_id: &FontId) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for segment in segments(line) {
+        match segment {
+            Segment::Text(text) => job.append(text, 0.0, style.text_format(font_id.clone())),
+            Segment::Sgr(params) => style.apply_sgr(params),
+        }
+    }
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::FontFamily;
+
+    const MONADO_FIXTURE: &str = include_str!("../tests/fixtures/monado-service.ansi");
+
+    fn font() -> FontId {
+        FontId::new(14.0, FontFamily::Monospace)
+    }
+
+    /// Text of each section of the line paired with its foreground color.
+    fn colored_sections(job: &LayoutJob) -> Vec<(&str, Color32)> {
+        job.sections
+            .iter()
+            .map(|s| (&job.text[s.byte_range.clone()], s.format.color))
+            .collect()
+    }
+
+    #[test]
+    fn segments_split_text_and_sgr() {
+        let parsed: Vec<_> = segments("a\x1b[1;31mb\x1b[0mc").collect();
+        assert_eq!(
+            parsed,
+            vec![
+                Segment::Text("a"),
+                Segment::Sgr("1;31"),
+                Segment::Text("b"),
+                Segment::Sgr("0"),
+                Segment::Text("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_sgr_sequences_are_dropped() {
+        assert_eq!(strip("\x1b[2Kline\x1b]0;title\x07 end\x1b(B"), "line end");
+        assert_eq!(strip("\x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\"), "link");
+        assert_eq!(strip("cut off \x1b[3"), "cut off ");
+    }
+
+    #[test]
+    fn reset_forms() {
+        let mut style = AnsiStyle::default();
+        style.apply_sgr("1;3;4;31;42");
+        assert!(style.bold && style.italic && style.underline);
+        style.apply_sgr("");
+        assert_eq!(style, AnsiStyle::default());
+
+        style.apply_sgr("1;2;31;44");
+        style.apply_sgr("22;39;49");
+        assert_eq!(style, AnsiStyle::default());
+    }
+
+    #[test]
+    fn standard_and_bright_colors() {
+        let mut style = AnsiStyle::default();
+        style.apply_sgr("33");
+        assert_eq!(style.foreground, Some(AnsiColor::Named(3)));
+        style.apply_sgr("93;104");
+        assert_eq!(style.foreground, Some(AnsiColor::Named(11)));
+        assert_eq!(style.background, Some(AnsiColor::Named(12)));
+
+        let mut bold = AnsiStyle::default();
+        bold.apply_sgr("1;31");
+        assert_eq!(
+            bold.text_format(font()).color,
+            AnsiColor::Named(9).to_color32()
+        );
+    }
+
+    #[test]
+    fn extended_colors() {
+        let mut style = AnsiStyle::default();
+        style.apply_sgr("38;5;196;48;2;10;20;30");
+        assert_eq!(style.foreground, Some(AnsiColor::Indexed(196)));
+        assert_eq!(style.background, Some(AnsiColor::Rgb(10, 20, 30)));
+        assert_eq!(
+            AnsiColor::Indexed(196).to_color32(),
+            Color32::from_rgb(255, 0, 0)
+        );
+        assert_eq!(
+            AnsiColor::Indexed(244).to_color32(),
+            Color32::from_rgb(128, 128, 128)
+        );
+
+        style.apply_sgr("38:2::1:2:3;48:5:21");
+        assert_eq!(style.foreground, Some(AnsiColor::Rgb(1, 2, 3)));
+        assert_eq!(style.background, Some(AnsiColor::Indexed(21)));
+
+        let format = style.text_format(font());
+        assert_eq!(format.color, Color32::from_rgb(1, 2, 3));
+        assert_eq!(format.background, AnsiColor::Indexed(21).to_color32());
+    }
+
+    #[test]
+    fn text_attributes() {
+        let mut style = AnsiStyle::default();
+        style.apply_sgr("3;4;9");
+        let format = style.text_format(font());
+        assert!(format.italics);
+        assert_ne!(format.underline, Stroke::NONE);
+        assert_ne!(format.strikethrough, Stroke::NONE);
+
+        style.apply_sgr("23;24;29;7;32");
+        let format = style.text_format(font());
+        assert!(!format.italics);
+        assert_eq!(format.underline, Stroke::NONE);
+        assert_eq!(format.background, AnsiColor::Named(2).to_color32());
+        assert_eq!(format.color, AnsiStyle::DEFAULT_BACKGROUND);
+    }
+
+    #[test]
+    fn style_carries_across_lines() {
+        let mut style = AnsiStyle::default();
+        layout_line("\x1b[35mstart of a long message", &mut style, &font());
+        let job = layout_line(
+            "continued on the next line\x1b[0m done",
+            &mut style,
+            &font(),
+        );
+        assert_eq!(
+            colored_sections(&job),
+            vec![
+                (
+                    "continued on the next line",
+                    AnsiColor::Named(5).to_color32()
+                ),
+                (" done", AnsiStyle::DEFAULT_FOREGROUND),
+            ]
+        );
+    }
+
+    #[test]
+    fn monado_fixture() {
+        let mut style = AnsiStyle::default();
+        let jobs: Vec<_> = MONADO_FIXTURE
+            .lines()
+            .map(|line| layout_line(line, &mut style, &font()))
+            .collect();
+
+        assert_eq!(jobs.len(), 6);
+        for (job, line) in jobs.iter().zip(MONADO_FIXTURE.lines()) {
+            assert!(!job.text.contains('\x1b'));
+            assert_eq!(job.text, strip(line));
+        }
+        assert_eq!(
+            jobs[0].text,
+            " INFO [ipc_server_main_common] Monado service starting"
+        );
+        assert_eq!(
+            colored_sections(&jobs[0])[0],
+            (" INFO", AnsiColor::Named(2).to_color32())
+        );
+        assert_eq!(
+            colored_sections(&jobs[1])[0],
+            ("DEBUG", AnsiColor::Named(6).to_color32())
+        );
+        assert_eq!(
+            colored_sections(&jobs[3])[0],
+            (" WARN", AnsiColor::Named(11).to_color32())
+        );
+        assert_eq!(
+            colored_sections(&jobs[4])[0],
+            ("ERROR", AnsiColor::Named(9).to_color32())
+        );
+        // Everything after the level is printed in the default color.
+        assert!(jobs
+            .iter()
+            .all(|job| colored_sections(job).last().unwrap().1 == AnsiStyle::DEFAULT_FOREGROUND));
+        assert_eq!(style, AnsiStyle::default());
+    }
+
+    #[test]
+    fn mixed_sequences_over_several_lines() {
+        // Written for this test, not captured from a real program.
+        let output = "Info: Loaded drivers: GlobalSceneSolver, HTCVive\n\
+            \x1b[0;33mWarning: Could not find config file, using defaults\n\
+            \x1b[0mLighthouse \x1b[38;5;208m0\x1b[39m solved at \x1b[38;2;120;200;255m(1.21, 2.05, 0.37)\x1b[0m\n\
+            \x1b[3mDevice T20 is now tracking\x1b[23m\x1b[m\n";
+        let mut style = AnsiStyle::default();
+        let jobs: Vec<_> = output
+            .lines()
+            .map(|line| layout_line(line, &mut style, &font()))
+            .collect();
+
+        assert_eq!(jobs.len(), 4);
+        assert_eq!(
+            jobs[0].text,
+            "Info: Loaded drivers: GlobalSceneSolver, HTCVive"
+        );
+        assert_eq!(
+            colored_sections(&jobs[1]),
+            vec![(
+                "Warning: Could not find config file, using defaults",
+                AnsiColor::Named(3).to_color32()
+            )]
+        );
+        // The lighthouse line uses 256 color and truecolor sequences.
+        assert_eq!(
+            colored_sections(&jobs[2]),
+            vec![
+                ("Lighthouse ", AnsiStyle::DEFAULT_FOREGROUND),
+                ("0", AnsiColor::Indexed(208).to_color32()),
+                (" solved at ", AnsiStyle::DEFAULT_FOREGROUND),
+                ("(1.21, 2.05, 0.37)", Color32::from_rgb(120, 200, 255)),
+            ]
+        );
+        assert!(jobs[3].sections.iter().all(|s| s.format.italics));
+        assert_eq!(style, AnsiStyle::default());
+    }
+}
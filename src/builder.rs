@@ -0,0 +1,334 @@
+use crate::{
+    child_output::OutputSender,
+    instance::MonadoInstance,
+    service::{ServiceExecutable, StartError},
+};
+use egui::{Color32, ComboBox, Context, Ui};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use subprocess::Exec;
+
+pub const MONADO_REPOSITORY: &str = "https://gitlab.freedesktop.org/monado/monado.git";
+
+/// Updates an existing checkout, `$1` is the source dir, `$2` the repository and `$3` the ref.
+/// origin is pointed at the repository first, the checkout might have been cloned from another.
+/// The ref is checked out detached, so a changed branch or a tag is switched to, not merged.
+const FETCH_SCRIPT: &str = r#"git -C "$1" remote set-url origin "$2" \
+    && git -C "$1" fetch --progress origin "$3" \
+    && git -C "$1" checkout --detach FETCH_HEAD"#;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BuildSettings {
+    pub repository: String,
+    pub git_ref: String,
+    pub build_type: BuildType,
+    pub cmake_args: String,
+}
+impl Default for BuildSettings {
+    fn default() -> Self {
+        BuildSettings {
+            repository: MONADO_REPOSITORY.to_string(),
+            git_ref: "main".to_string(),
+            build_type: BuildType::default(),
+            cmake_args: String::new(),
+        }
+    }
+}
+impl BuildSettings {
+    pub fn update(
+        inst: &mut MonadoInstance,
+        ctx: &Context,
+        stdout_sender: &OutputSender,
+    ) -> bool {
+        let mut changed = false;
+        egui::Window::new("Build")
+            .collapsible(true)
+            .default_open(false)
+            .show(ctx, |ui| {
+                changed = BuildSettings::settings_grid(inst, ui);
+                ui.separator();
+                BuildSettings::step_buttons(inst, ui, stdout_sender);
+            });
+        changed
+    }
+
+    fn settings_grid(inst: &mut MonadoInstance, ui: &mut Ui) -> bool {
+        let settings = &mut inst.build_settings;
+        let mut changed = false;
+        egui::Grid::new("build_settings")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Repository");
+                changed |= ui.text_edit_singleline(&mut settings.repository).changed();
+                ui.end_row();
+
+                ui.label("Branch/Tag");
+                changed |= ui.text_edit_singleline(&mut settings.git_ref).changed();
+                ui.end_row();
+
+                ui.label("Build Type");
+                ComboBox::from_id_source("build_type")
+                    .selected_text(settings.build_type.to_string())
+                    .show_ui(ui, |ui| {
+                        for build_type in [
+                            BuildType::Debug,
+                            BuildType::RelWithDebInfo,
+                            BuildType::Release,
+                        ] {
+                            let text = build_type.to_string();
+                            changed |= ui
+                                .selectable_value(&mut settings.build_type, build_type, text)
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("CMake Arguments").on_hover_text(
+                    "Extra arguments passed to cmake when configuring, split like a shell would.",
+                );
+                changed |= ui.text_edit_singleline(&mut settings.cmake_args).changed();
+                ui.end_row();
+            });
+        changed
+    }
+
+    fn step_buttons(
+        inst: &mut MonadoInstance,
+        ui: &mut Ui,
+        stdout_sender: &OutputSender,
+    ) {
+        let running_step = inst.build_process.as_ref().map(|(step, _)| *step);
+        ui.horizontal(|ui| {
+            for step in [
+                BuildStep::Fetch,
+                BuildStep::Configure,
+                BuildStep::Build,
+                BuildStep::Install,
+            ] {
+                let clicked = ui
+                    .add_enabled(running_step.is_none(), egui::Button::new(step.to_string()))
+                    .clicked();
+                if clicked {
+                    if let Err(err) = inst.start_build_step(step, stdout_sender.clone()) {
+                        stdout_sender
+                            .clone()
+                            .send(format!("Unable to start {}: {}\n", step, err));
+                    }
+                }
+            }
+        });
+        if let Some(step) = running_step {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("Running {}…", step));
+                if ui.button("Cancel").clicked() {
+                    if let Some((_, child)) = inst.build_process.as_mut() {
+                        let _ = child.kill();
+                    }
+                }
+            });
+        }
+        let installed = inst.built_monado_service().is_file();
+        match inst.service_settings.executable {
+            ServiceExecutable::Automatic if installed => {
+                ui.colored_label(
+                    Color32::LIGHT_GREEN,
+                    "Using this instance's monado-service.",
+                );
+            }
+            ServiceExecutable::Automatic => {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    "Not installed yet, using the system monado-service.",
+                );
+            }
+            ServiceExecutable::InstanceBuild if !installed => {
+                ui.colored_label(
+                    Color32::LIGHT_RED,
+                    "Not installed yet, monado-service can't be started.",
+                );
+            }
+            executable => {
+                ui.label(format!("The Service window selects: {}.", executable));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Copy, Clone, Default)]
+pub enum BuildType {
+    Debug,
+    #[default]
+    RelWithDebInfo,
+    Release,
+}
+impl fmt::Display for BuildType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildType::Debug => write!(f, "Debug"),
+            BuildType::RelWithDebInfo => write!(f, "RelWithDebInfo"),
+            BuildType::Release => write!(f, "Release"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BuildStep {
+    Fetch,
+    Configure,
+    Build,
+    Install,
+}
+impl fmt::Display for BuildStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildStep::Fetch => write!(f, "Fetch Source"),
+            BuildStep::Configure => write!(f, "Configure"),
+            BuildStep::Build => write!(f, "Build"),
+            BuildStep::Install => write!(f, "Install"),
+        }
+    }
+}
+impl BuildStep {
+    pub fn program(&self) -> &'static str {
+        match self {
+            BuildStep::Fetch => "git",
+            _ => "cmake",
+        }
+    }
+
+    pub fn command(&self, inst: &MonadoInstance) -> Result<Exec, StartError> {
+        let settings = &inst.build_settings;
+        let source_dir = inst.source_dir();
+        let build_dir = inst.build_dir();
+        let cmake_args = shell_words::split(&settings.cmake_args).map_err(|err| {
+            StartError::InvalidSettings(format!("Invalid CMake arguments: {}", err))
+        })?;
+        Ok(match self {
+            BuildStep::Fetch if source_dir.join(".git").exists() => Exec::cmd("sh")
+                .args(&["-c", FETCH_SCRIPT, "sh"])
+                .arg(&source_dir)
+                .arg(&settings.repository)
+                .arg(&settings.git_ref),
+            BuildStep::Fetch => Exec::cmd("git")
+                .args(&["clone", "--progress", "--branch", &settings.git_ref])
+                .arg(&settings.repository)
+                .arg(&source_dir),
+            BuildStep::Configure => Exec::cmd("cmake")
+                .arg("-S")
+                .arg(&source_dir)
+                .arg("-B")
+                .arg(&build_dir)
+                .arg(format!(
+                    "-DCMAKE_INSTALL_PREFIX={}",
+                    inst.install_dir().display()
+                ))
+                .arg(format!("-DCMAKE_BUILD_TYPE={}", settings.build_type))
+                .args(&cmake_args),
+            BuildStep::Build => Exec::cmd("cmake")
+                .arg("--build")
+                .arg(&build_dir)
+                .arg("--parallel"),
+            BuildStep::Install => Exec::cmd("cmake").arg("--install").arg(&build_dir),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use subprocess::Redirection;
+
+    #[test]
+    fn splits_cmake_args_like_a_shell() {
+        let mut instance = MonadoInstance::default();
+        instance.build_settings.cmake_args =
+            "-DXRT_HAVE_SDL2=OFF '-DCMAKE_C_FLAGS=-O2 -g' -G\\ Ninja".to_string();
+        let command = BuildStep::Configure.command(&instance).unwrap();
+        assert!(command
+            .to_cmdline_lossy()
+            .ends_with("'-DXRT_HAVE_SDL2=OFF' '-DCMAKE_C_FLAGS=-O2 -g' '-G Ninja'"));
+
+        instance.build_settings.cmake_args = "'-DUNCLOSED=1".to_string();
+        assert_eq!(
+            BuildStep::Configure
+                .command(&instance)
+                .unwrap_err()
+                .to_string(),
+            "Invalid CMake arguments: missing closing quote"
+        );
+    }
+
+    #[test]
+    fn fetch_switches_repository_and_ref() {
+        let dir = std::env::temp_dir().join(format!("rex-fetch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut instance = MonadoInstance::create_load(&dir, "fetch".to_string()).unwrap();
+        let git = |dir: &Path, args: &[&str]| {
+            Exec::cmd("git")
+                .args(&["-c", "user.name=rex", "-c", "user.email=rex@localhost"])
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .stdout(Redirection::Pipe)
+                .stderr(Redirection::Merge)
+                .capture()
+                .unwrap()
+                .stdout_str()
+        };
+        let fetch = |instance: &MonadoInstance| {
+            let capture = BuildStep::Fetch
+                .command(instance)
+                .unwrap()
+                .stdout(Redirection::Pipe)
+                .stderr(Redirection::Merge)
+                .capture()
+                .unwrap();
+            assert!(capture.success(), "{}", capture.stdout_str());
+        };
+
+        // main, and a tag on a branch that diverged from it.
+        let upstream = dir.join("upstream");
+        std::fs::create_dir_all(&upstream).unwrap();
+        let commit = |message| {
+            git(
+                &upstream,
+                &["commit", "--quiet", "--allow-empty", "-m", message],
+            )
+        };
+        git(&upstream, &["init", "--quiet", "--initial-branch=main"]);
+        commit("1");
+        git(&upstream, &["checkout", "--quiet", "-b", "other"]);
+        commit("2");
+        git(&upstream, &["tag", "v2"]);
+        git(&upstream, &["checkout", "--quiet", "main"]);
+        commit("3");
+
+        let source_dir = instance.source_dir();
+        std::fs::create_dir_all(&source_dir).unwrap();
+        git(&source_dir, &["init", "--quiet"]);
+        git(&source_dir, &["remote", "add", "origin", MONADO_REPOSITORY]);
+
+        instance.build_settings.repository = upstream.to_string_lossy().into_owned();
+        fetch(&instance);
+        assert_eq!(
+            git(&source_dir, &["remote", "get-url", "origin"]).trim(),
+            upstream.to_string_lossy()
+        );
+        assert_eq!(
+            git(&source_dir, &["rev-parse", "HEAD"]),
+            git(&upstream, &["rev-parse", "main"])
+        );
+
+        instance.build_settings.git_ref = "v2".to_string();
+        fetch(&instance);
+        assert_eq!(
+            git(&source_dir, &["rev-parse", "HEAD"]),
+            git(&upstream, &["rev-parse", "v2^{commit}"])
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
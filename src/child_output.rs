@@ -0,0 +1,237 @@
+use egui::Context;
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read},
+    sync::mpsc::{SyncSender, TrySendError},
+};
+
+/// Lines a channel holds before output is dropped or, without a UI, the sender blocks.
+pub const OUTPUT_CHANNEL_SIZE: usize = 64000;
+
+/// Sends output to the console, each clone counts the lines it had to drop on its own.
+#[derive(Clone)]
+pub struct OutputSender {
+    sender: SyncSender<String>,
+    /// Woken up for every line, `None` when nothing is drawn and sending may block instead.
+    repaint: Option<Context>,
+    dropped: usize,
+}
+impl OutputSender {
+    pub fn new(sender: SyncSender<String>, repaint: Option<Context>) -> Self {
+        OutputSender {
+            sender,
+            repaint,
+            dropped: 0,
+        }
+    }
+
+    pub fn repaint_context(&self) -> Option<Context> {
+        self.repaint.clone()
+    }
+
+    /// Returns false once the receiving end is gone.
+    ///
+    /// A full channel never blocks a UI's sender, the child would stall writing to its
+    /// pipe. Lines are dropped instead and summarised once there is room again.
+    pub fn send(&mut self, line: String) -> bool {
+        let Some(ctx) = &self.repaint else {
+            return self.sender.send(line).is_ok();
+        };
+        if self.dropped > 0 {
+            let summary = format!(
+                "[rex] {} lines dropped, the console couldn't keep up.\n",
+                self.dropped
+            );
+            match self.sender.try_send(summary) {
+                Ok(()) => self.dropped = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        let sent = match self.sender.try_send(line) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        };
+        ctx.request_repaint();
+        sent
+    }
+}
+
+/// Reads a child's output until the pipe closes, calling `on_line` with every line.
+///
+/// Lines keep their trailing newline, a last line without one gets it added. Invalid
+/// UTF-8 is replaced rather than ending the output. Stops early once `on_line` returns false.
+pub fn forward_lines(output: impl Read, mut on_line: impl FnMut(String) -> bool) {
+    let mut reader = BufReader::new(output);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => return,
+            Ok(_) => {
+                let mut line = String::from_utf8_lossy(&buf).into_owned();
+                if !line.ends_with('\n') {
+                    line.push('\n');
+                }
+                if !on_line(line) {
+                    return;
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instance::MonadoInstance, log_options::LoggingEnvVars, service::ServiceStatus};
+    use std::{os::unix::fs::PermissionsExt, sync::mpsc::sync_channel, thread, time::Duration};
+
+    #[test]
+    fn splits_lines() {
+        let mut lines = Vec::new();
+        forward_lines(&b"first\n\nsecond\r\nlast"[..], |line| {
+            lines.push(line);
+            true
+        });
+        assert_eq!(lines, ["first\n", "\n", "second\r\n", "last\n"]);
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        let mut lines = Vec::new();
+        forward_lines(&b"ok \xff\xfe bytes\n\xe2\x9c\x93\n"[..], |line| {
+            lines.push(line);
+            true
+        });
+        assert_eq!(lines, ["ok \u{fffd}\u{fffd} bytes\n", "\u{2713}\n"]);
+    }
+
+    #[test]
+    fn stops_when_told() {
+        let mut lines = Vec::new();
+        forward_lines(&b"a\nb\nc\n"[..], |line| {
+            lines.push(line);
+            lines.len() < 2
+        });
+        assert_eq!(lines, ["a\n", "b\n"]);
+    }
+
+    #[test]
+    fn summarises_dropped_lines() {
+        let (sender, receiver) = sync_channel(2);
+        let mut sender = OutputSender::new(sender, Some(Context::default()));
+        for i in 0..5 {
+            assert!(sender.send(format!("{}\n", i)));
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["0\n", "1\n"]);
+
+        assert!(sender.send("5\n".to_string()));
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["[rex] 3 lines dropped, the console couldn't keep up.\n", "5\n"]
+        );
+
+        drop(receiver);
+        assert!(!sender.send("6\n".to_string()));
+    }
+
+    #[test]
+    fn fake_service() {
+        let dir = std::env::temp_dir().join(format!("rex-fake-service-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut instance = MonadoInstance::create_load(&dir, "fake".to_string()).unwrap();
+
+        // Slow, partial and invalid output, then a failing exit.
+        let service = instance.built_monado_service();
+        std::fs::create_dir_all(service.parent().unwrap()).unwrap();
+        std::fs::write(
+            &service,
+            "#!/bin/sh\n\
+             printf 'INFO [main] starting\\n'\n\
+             sleep 0.2\n\
+             printf 'half a '\n\
+             sleep 0.2\n\
+             printf 'line\\n\\377\\n'\n\
+             printf 'no newline'\n\
+             exit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&service, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (sender, receiver) = sync_channel(16);
+        let sender = OutputSender::new(sender, None);
+        let logging = LoggingEnvVars::default();
+        instance.start_monado(&logging, sender.clone()).unwrap();
+
+        let mut output = Vec::new();
+        while let Ok(line) = receiver.recv_timeout(Duration::from_secs(5)) {
+            output.push(line);
+            if output.len() == 4 {
+                break;
+            }
+        }
+        assert_eq!(
+            output,
+            [
+                "INFO [main] starting\n",
+                "half a line\n",
+                "\u{fffd}\n",
+                "no newline\n"
+            ]
+        );
+
+        let mut status = None;
+        for _ in 0..100 {
+            if instance.poll_monado(&logging).is_some() {
+                status = Some(instance.status());
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(status, Some(ServiceStatus::Exited(3)));
+        assert!(instance.child.is_none());
+        assert!(!instance.pid_file().exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn start_clears_old_output() {
+        let dir = std::env::temp_dir().join(format!("rex-clear-console-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut instance = MonadoInstance::create_load(&dir, "clear".to_string()).unwrap();
+        let service = instance.built_monado_service();
+        std::fs::create_dir_all(service.parent().unwrap()).unwrap();
+        std::fs::write(&service, "#!/bin/sh\nexit 0\n").unwrap();
+        std::fs::set_permissions(&service, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let logging = LoggingEnvVars::default();
+        let sender = instance.console.sender();
+
+        // A failed start keeps the old output and its error.
+        instance.console.buffer.push_str("old\n");
+        instance.service_settings.args = "'unterminated".to_string();
+        instance.clear_console = true;
+        assert!(instance.start_monado(&logging, sender.clone()).is_err());
+        instance.console.drain();
+        assert!(instance.console.buffer.plain_text().starts_with("old\n"));
+
+        // Output still waiting in the channel is old too.
+        sender.clone().send("also old\n".to_string());
+        instance.service_settings.args.clear();
+        instance.clear_console = true;
+        instance.start_monado(&logging, sender).unwrap();
+        assert!(!instance.console.buffer.plain_text().contains("old"));
+
+        instance.child.take().unwrap().wait().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{
+    builder::BuildStep,
+    child_output::{OutputSender, OUTPUT_CHANNEL_SIZE},
+    instance::MonadoInstance,
+    log_options::LoggingEnvVars,
+};
+use nix::sys::signal::{self, SigHandler, Signal};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use subprocess::ExitStatus;
+
+const USAGE: &str = "Usage: rex [COMMAND]
+
+Without a command the GUI is started.
+
+Commands:
+  list                        List instances and whether they are running
+  run <instance>              Run an instance's monado-service in the foreground
+  stop <instance>             Stop an instance's monado-service started by another rex
+  wait <instance> [seconds]   Wait until an instance's monado-service accepts clients, 30s by default
+  env <instance>              Print the environment monado-service would be started with
+  build <instance> [step...]  Run build steps (fetch, configure, build, install), all by default
+  help                        Show this message";
+
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs a headless command and returns the process exit code.
+pub fn run(monado_instance_dir: &Path, args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["list"] => list(monado_instance_dir),
+        ["run", name] => with_instance(monado_instance_dir, name, run_instance),
+        ["stop", name] => with_instance(monado_instance_dir, name, stop_instance),
+        ["wait", name] => with_instance(monado_instance_dir, name, |instance| {
+            wait_instance(instance, DEFAULT_WAIT_TIMEOUT)
+        }),
+        ["wait", name, timeout] => match timeout.parse::<f32>() {
+            Ok(timeout) if timeout >= 0.0 => with_instance(monado_instance_dir, name, |instance| {
+                wait_instance(instance, Duration::from_secs_f32(timeout))
+            }),
+            _ => {
+                eprintln!("Invalid timeout '{}'.\n\n{}", timeout, USAGE);
+                2
+            }
+        },
+        ["env", name] => with_instance(monado_instance_dir, name, print_env),
+        ["build", name, steps @ ..] => {
+            let steps = if steps.is_empty() {
+                Ok(vec![
+                    BuildStep::Fetch,
+                    BuildStep::Configure,
+                    BuildStep::Build,
+                    BuildStep::Install,
+                ])
+            } else {
+                steps.iter().map(|s| parse_build_step(s)).collect()
+            };
+            match steps {
+                Ok(steps) => with_instance(monado_instance_dir, name, |instance| {
+                    build_instance(instance, &steps)
+                }),
+                Err(step) => {
+                    eprintln!("Unknown build step '{}'.\n\n{}", step, USAGE);
+                    2
+                }
+            }
+        }
+        ["help" | "--help" | "-h"] => {
+            println!("{}", USAGE);
+            0
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+fn parse_build_step(step: &str) -> Result<BuildStep, String> {
+    match step {
+        "fetch" => Ok(BuildStep::Fetch),
+        "configure" => Ok(BuildStep::Configure),
+        "build" => Ok(BuildStep::Build),
+        "install" => Ok(BuildStep::Install),
+        _ => Err(step.to_string()),
+    }
+}
+
+fn instance_names(monado_instance_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(monado_instance_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.ok())
+        .filter(|d| d.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|d| Some(d.file_name().to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+fn with_instance(
+    monado_instance_dir: &Path,
+    name: &str,
+    f: impl FnOnce(&mut MonadoInstance) -> i32,
+) -> i32 {
+    if !instance_names(monado_instance_dir)
+        .iter()
+        .any(|n| n == name)
+    {
+        eprintln!(
+            "No instance named '{}' in {}",
+            name,
+            monado_instance_dir.display()
+        );
+        return 1;
+    }
+    match MonadoInstance::create_load(monado_instance_dir, name.to_string()) {
+        Ok(mut instance) => f(&mut instance),
+        Err(err) => {
+            eprintln!("Error loading instance '{}': {}", name, err);
+            1
+        }
+    }
+}
+
+fn load_logging_env_vars() -> LoggingEnvVars {
+    confy::load("monado", "logging").unwrap_or_else(|err| {
+        eprintln!("Error loading logging config, using defaults: {}", err);
+        LoggingEnvVars::default()
+    })
+}
+
+fn print_output(stdout_receiver: Receiver<String>) {
+    for line in stdout_receiver {
+        print!("{}", line);
+    }
+}
+
+fn exit_code(status: ExitStatus) -> i32 {
+    match status {
+        ExitStatus::Exited(code) => code as i32,
+        ExitStatus::Signaled(signal) => 128 + signal as i32,
+        ExitStatus::Other(code) => code,
+        ExitStatus::Undetermined => 1,
+    }
+}
+
+fn list(monado_instance_dir: &Path) -> i32 {
+    for name in instance_names(monado_instance_dir) {
+        let instance = MonadoInstance::create_load(monado_instance_dir, name.clone()).ok();
+        match instance.as_ref().and_then(|i| Some((i, i.running_pid()?))) {
+            Some((instance, pid)) if instance.ready_file().exists() => {
+                println!("{}\tready (pid {})", name, pid)
+            }
+            Some((_, pid)) => println!("{}\trunning (pid {})", name, pid),
+            None => println!("{}", name),
+        }
+    }
+    0
+}
+
+/// Set by Ctrl+C, `run_instance` stops monado-service without restarting it.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Runs monado-service like the GUI would, with its restart policy and "with service" clients,
+/// until it exits without being restarted.
+fn run_instance(instance: &mut MonadoInstance) -> i32 {
+    if let Some(pid) = instance.running_pid() {
+        eprintln!("Instance is already running (pid {}).", pid);
+        return 1;
+    }
+    let logging_env_vars = load_logging_env_vars();
+    let started = instance.start_monado(&logging_env_vars, instance.console.sender());
+    if started.is_err() {
+        // Already reported through the output.
+        print_console(instance, Duration::ZERO);
+        return 1;
+    }
+    if let Some(socket) = &instance.stale_socket {
+        eprintln!(
+            "A stale IPC socket is left at {}, monado-service may not be able to start.",
+            socket.display()
+        );
+    }
+    // Ctrl+C also reaches monado-service and the clients through the process group, stay alive
+    // to report how they exit.
+    let _ = unsafe { signal::signal(Signal::SIGINT, SigHandler::Handler(on_interrupt)) };
+
+    let mut last_status = None;
+    loop {
+        print_console(instance, Duration::from_millis(100));
+        if INTERRUPTED.swap(false, Ordering::Relaxed) {
+            // Its exit was asked for, don't restart it.
+            let _ = instance.stop_monado();
+        }
+        if let Some(status) = instance.poll_monado(&logging_env_vars) {
+            last_status = Some(status);
+        }
+        instance.poll_clients();
+        if instance.child.is_none() && instance.pending_restart().is_none() {
+            break;
+        }
+    }
+
+    // Clients can't do anything without monado-service.
+    instance.stop_clients();
+    let deadline = Instant::now() + instance.service_settings.stop_timeout();
+    while instance.clients.iter().any(|c| c.child.is_some()) && Instant::now() < deadline {
+        print_console(instance, Duration::from_millis(50));
+        instance.poll_clients();
+    }
+    instance.kill_clients();
+    // What the output readers still had.
+    print_console(instance, Duration::from_millis(100));
+
+    match last_status {
+        Some(status) => exit_code(status),
+        None => 1,
+    }
+}
+
+/// Prints the service's and the clients' output, waiting up to `timeout` for the service's.
+fn print_console(instance: &MonadoInstance, timeout: Duration) {
+    for line in instance.console.take_output(timeout) {
+        print!("{}", line);
+    }
+    for client in &instance.clients {
+        for line in client.console.take_output(Duration::ZERO) {
+            print!("[{}] {}", client.name, line);
+        }
+    }
+}
+
+fn stop_instance(instance: &mut MonadoInstance) -> i32 {
+    let Some(pid) = instance.running_pid() else {
+        eprintln!("Instance is not running.");
+        return 1;
+    };
+    let settings = &instance.service_settings;
+    if let Err(err) = signal::kill(pid, settings.stop_signal.signal()) {
+        eprintln!("Unable to stop monado-service (pid {}): {}", pid, err);
+        return 1;
+    }
+
+    // The rex that started it reaps it, only watch for the pid to go away.
+    let deadline = Instant::now() + settings.stop_timeout();
+    while Instant::now() < deadline {
+        if signal::kill(pid, None).is_err() {
+            return 0;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    eprintln!(
+        "monado-service (pid {}) did not stop within {:.1}s, killing it.",
+        pid, settings.stop_timeout
+    );
+    match signal::kill(pid, Signal::SIGKILL) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Unable to kill monado-service (pid {}): {}", pid, err);
+            1
+        }
+    }
+}
+
+fn wait_instance(instance: &mut MonadoInstance, timeout: Duration) -> i32 {
+    // The rex running monado-service marks it ready, see `MonadoInstance::poll_ready`.
+    let deadline = Instant::now() + timeout;
+    loop {
+        if instance.running_pid().is_some() && instance.ready_file().exists() {
+            return 0;
+        }
+        if Instant::now() >= deadline {
+            eprintln!(
+                "monado-service did not become ready within {:.1}s.",
+                timeout.as_secs_f32()
+            );
+            return 1;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn print_env(instance: &mut MonadoInstance) -> i32 {
+    for (key, value) in instance.effective_env(&load_logging_env_vars()).0 {
+        println!("{}={}", key, value);
+    }
+    0
+}
+
+fn build_instance(instance: &mut MonadoInstance, steps: &[BuildStep]) -> i32 {
+    for step in steps {
+        let (stdout_sender, stdout_receiver) = sync_channel(OUTPUT_CHANNEL_SIZE);
+        if let Err(err) = instance.start_build_step(*step, OutputSender::new(stdout_sender, None)) {
+            eprintln!("Unable to start {}: {}", step, err);
+            return 1;
+        }
+        print_output(stdout_receiver);
+
+        let Some((_, mut child)) = instance.build_process.take() else {
+            return 1;
+        };
+        match child.wait() {
+            Ok(ExitStatus::Exited(0)) => {}
+            Ok(status) => {
+                eprintln!("{} failed: {:?}", step, status);
+                return exit_code(status);
+            }
+            Err(err) => {
+                eprintln!("Unable to wait for {}: {}", step, err);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clients::{ClientProfile, ClientWait},
+        service::RestartPolicy,
+    };
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn run_restarts_and_starts_clients() {
+        let dir = std::env::temp_dir().join(format!("rex-cli-run-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut instance = MonadoInstance::create_load(&dir, "cli".to_string()).unwrap();
+
+        // Crashes the first time, exits cleanly the second.
+        let service = instance.built_monado_service();
+        std::fs::create_dir_all(service.parent().unwrap()).unwrap();
+        std::fs::write(
+            &service,
+            "#!/bin/sh\n\
+             if [ -e \"$0.crashed\" ]; then exit 0; fi\n\
+             touch \"$0.crashed\"\n\
+             sleep 0.3\n\
+             exit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&service, std::fs::Permissions::from_mode(0o755)).unwrap();
+        instance.service_settings.restart = RestartPolicy::OnFailure;
+        instance.service_settings.restart_delay = 0.0;
+
+        let client_ran = dir.join("client-ran");
+        instance.client_settings.profiles.push(ClientProfile {
+            name: "client".to_string(),
+            command: "sh".to_string(),
+            args: shell_words::join(["-c", &format!("echo ran >> '{}'", client_ran.display())]),
+            with_service: true,
+            wait: ClientWait::Immediately,
+            ..ClientProfile::default()
+        });
+
+        // Only the restarted run exits with 0.
+        assert_eq!(run_instance(&mut instance), 0);
+        assert!(std::fs::read_to_string(&client_ran).is_ok_and(|ran| !ran.is_empty()));
+        assert!(instance.clients.iter().all(|c| c.child.is_none()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
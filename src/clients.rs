@@ -0,0 +1,442 @@
+use crate::{
+    child_output::forward_lines,
+    console::{Console, ConsoleView},
+    env_var::{CustomEnvVar, EnvList, EnvVars},
+    instance::MonadoInstance,
+    service::{find_executable, ServiceStatus, StartError},
+};
+use egui::{Color32, ComboBox, Context, DragValue};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+use subprocess::{Exec, Popen, Redirection};
+
+/// OpenXR apps started alongside an instance's monado-service.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClientSettings {
+    /// Stop every client when monado-service is stopped from rex.
+    pub stop_with_service: bool,
+    pub profiles: Vec<ClientProfile>,
+}
+impl Default for ClientSettings {
+    fn default() -> Self {
+        ClientSettings {
+            stop_with_service: true,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ClientProfile {
+    pub name: String,
+    pub command: String,
+    /// Split like a shell would.
+    pub args: String,
+    /// Launch every time monado-service is started.
+    pub with_service: bool,
+    pub wait: ClientWait,
+    /// Seconds to wait when `wait` is `Delay`.
+    pub delay: f32,
+    /// Set on top of the variables that point the client at this instance.
+    pub env: Vec<CustomEnvVar>,
+    #[serde(skip)]
+    pub argv_check: Option<ArgvCheck>,
+}
+impl Default for ClientProfile {
+    fn default() -> Self {
+        ClientProfile {
+            name: "hello_xr".to_string(),
+            command: "hello_xr".to_string(),
+            args: "-g Vulkan2".to_string(),
+            with_service: false,
+            wait: ClientWait::default(),
+            delay: 2.0,
+            env: Vec::new(),
+            argv_check: None,
+        }
+    }
+}
+impl ClientProfile {
+    pub fn argv(&self) -> Result<Vec<String>, StartError> {
+        let args = shell_words::split(&self.args)
+            .map_err(|err| StartError::InvalidSettings(format!("Invalid arguments: {}", err)))?;
+        let program = find_executable(Path::new(self.command.trim()))?;
+        let mut argv = vec![program.to_string_lossy().into_owned()];
+        argv.extend(args);
+        Ok(argv)
+    }
+
+    /// `argv`'s error for the settings window, only searched for on PATH again once the
+    /// command or arguments change.
+    fn argv_error(&mut self) -> Option<&str> {
+        let settings = (self.command.clone(), self.args.clone());
+        if self.argv_check.as_ref().map(|c| &c.settings) != Some(&settings) {
+            let error = self.argv().err().map(|err| err.to_string());
+            self.argv_check = Some(ArgvCheck { settings, error });
+        }
+        self.argv_check.as_ref().unwrap().error.as_deref()
+    }
+}
+
+/// The profile window's check of the command line, kept until the command or arguments change.
+#[derive(Debug, Clone)]
+pub struct ArgvCheck {
+    settings: (String, String),
+    error: Option<String>,
+}
+
+/// When a launched client is actually started.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClientWait {
+    Immediately,
+    Delay,
+    /// Until monado-service is up and clients can connect.
+    #[default]
+    ServiceReady,
+}
+impl ClientWait {
+    pub const ALL: [ClientWait; 3] = [
+        ClientWait::Immediately,
+        ClientWait::Delay,
+        ClientWait::ServiceReady,
+    ];
+}
+impl fmt::Display for ClientWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientWait::Immediately => write!(f, "Immediately"),
+            ClientWait::Delay => write!(f, "After Delay"),
+            ClientWait::ServiceReady => write!(f, "When Service Is Ready"),
+        }
+    }
+}
+
+/// A launched client, kept after it exits so its output can still be read.
+pub struct Client {
+    pub name: String,
+    pub child: Option<Popen>,
+    pub console: Console,
+    exit: Option<ServiceStatus>,
+}
+impl Client {
+    pub fn status(&self) -> ServiceStatus {
+        match (&self.child, self.exit) {
+            (Some(_), _) => ServiceStatus::Running,
+            (None, Some(exit)) => exit,
+            (None, None) => ServiceStatus::NotStarted,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.terminate();
+        }
+    }
+}
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("name", &self.name)
+            .field("status", &self.status())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A client waiting for its `ClientWait` before it is started.
+#[derive(Debug)]
+pub struct PendingClient {
+    pub profile: ClientProfile,
+    since: Instant,
+}
+
+impl MonadoInstance {
+    /// Queues `profile`, `poll_clients` starts it once its wait is over.
+    pub fn launch_client(&mut self, profile: ClientProfile) {
+        self.pending_clients.push(PendingClient {
+            profile,
+            since: Instant::now(),
+        });
+    }
+
+    /// Queues every profile that starts along with monado-service.
+    pub fn launch_service_clients(&mut self) {
+        let profiles: Vec<ClientProfile> = self
+            .client_settings
+            .profiles
+            .iter()
+            .filter(|p| p.with_service)
+            .cloned()
+            .collect();
+        for profile in profiles {
+            self.launch_client(profile);
+        }
+    }
+
+    pub fn stop_clients(&mut self) {
+        self.pending_clients.clear();
+        for client in &mut self.clients {
+            client.stop();
+        }
+    }
+
+    pub fn client_command(&self, profile: &ClientProfile) -> Result<Exec, StartError> {
+        let argv = profile.argv()?;
+        let mut command = Exec::cmd(&argv[0]).args(&argv[1..]);
+        for (key, value) in self.client_env().0 {
+            command = command.env(key, value);
+        }
+        for var in profile.env.iter().filter(|v| v.is_active()) {
+            command = command.env(var.key.trim(), &var.value);
+        }
+        Ok(command
+            .stderr(Redirection::Merge)
+            .stdout(Redirection::Pipe)
+            .stdin(Redirection::None))
+    }
+
+    /// Starts a client right away, a failure is written to its console.
+    fn start_client(&mut self, profile: &ClientProfile) {
+        let console = self.console.sibling();
+        let mut stdout_sender = console.sender();
+        let mut client = Client {
+            name: profile.name.clone(),
+            child: None,
+            console,
+            exit: None,
+        };
+        let started = self.client_command(profile).and_then(|command| {
+            stdout_sender.send(format!("> {}\n", command.to_cmdline_lossy()));
+            command
+                .popen()
+                .map_err(|err| StartError::from_popen(Path::new(&profile.command), err))
+        });
+        match started {
+            Ok(mut child) => {
+                if let Some(stdout) = child.stdout.take() {
+                    thread::spawn(move || forward_lines(stdout, |line| stdout_sender.send(line)));
+                }
+                client.child = Some(child);
+            }
+            Err(err) => {
+                stdout_sender.send(format!("Unable to start {}: {}\n", profile.name, err));
+                client.exit = Some(ServiceStatus::NotStarted);
+            }
+        }
+        // A client launched again replaces its finished tab instead of adding another.
+        match self
+            .clients
+            .iter()
+            .position(|c| c.name == client.name && c.child.is_none())
+        {
+            Some(i) => self.clients[i] = client,
+            None => self.clients.push(client),
+        }
+    }
+
+    /// Starts clients whose wait is over, reaps the ones that exited and moves their output
+    /// into their consoles. Returns whether a client is still waiting.
+    pub fn poll_clients(&mut self) -> bool {
+        let ready = self.status() == ServiceStatus::Running;
+        let (start, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_clients)
+            .into_iter()
+            .partition(|pending| match pending.profile.wait {
+                ClientWait::Immediately => true,
+                ClientWait::Delay => {
+                    pending.since.elapsed()
+                        >= Duration::from_secs_f32(pending.profile.delay.max(0.0))
+                }
+                ClientWait::ServiceReady => ready,
+            });
+        self.pending_clients = waiting;
+        for pending in start {
+            self.start_client(&pending.profile);
+        }
+
+        for client in &mut self.clients {
+            client.console.drain();
+            let Some(child) = client.child.as_mut() else {
+                continue;
+            };
+            let Some(status) = child.poll() else {
+                continue;
+            };
+            let status = ServiceStatus::from_exit_status(status);
+            client
+                .console
+                .sender()
+                .send(format!("{}: {}.\n", client.name, status));
+            client.exit = Some(status);
+            client.child = None;
+        }
+        !self.pending_clients.is_empty()
+    }
+
+    /// Forgets a finished client and its output.
+    pub fn close_client(&mut self, index: usize) {
+        self.clients.remove(index);
+        self.shown_console = match self.shown_console {
+            ConsoleView::Client(shown) if shown == index => ConsoleView::Service,
+            ConsoleView::Client(shown) if shown > index => ConsoleView::Client(shown - 1),
+            shown => shown,
+        };
+    }
+
+    /// Kills every client and waits for them, for when the instance goes away.
+    pub fn kill_clients(&mut self) {
+        self.pending_clients.clear();
+        for client in &mut self.clients {
+            if let Some(mut child) = client.child.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+pub fn update(inst: &mut MonadoInstance, ctx: &Context) -> bool {
+    let mut changed = false;
+    egui::Window::new("Clients")
+        .default_open(false)
+        .collapsible(true)
+        .show(ctx, |ui| {
+            changed |= ui
+                .checkbox(
+                    &mut inst.client_settings.stop_with_service,
+                    "Stop clients with monado-service",
+                )
+                .changed();
+
+            let client_env = inst.client_env();
+            let mut launch = None;
+            let mut remove = None;
+            for (i, profile) in inst.client_settings.profiles.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    egui::CollapsingHeader::new(&profile.name)
+                        .id_source("profile")
+                        .show(ui, |ui| {
+                            changed |= profile_settings(profile, ui, &client_env);
+                            ui.horizontal(|ui| {
+                                if ui.button("Launch").clicked() {
+                                    launch = Some(profile.clone());
+                                }
+                                if ui.button("Remove Profile").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        });
+                });
+            }
+            if let Some(profile) = launch {
+                inst.launch_client(profile);
+            }
+            if let Some(i) = remove {
+                inst.client_settings.profiles.remove(i);
+                changed = true;
+            }
+            if ui.button("Add Profile").clicked() {
+                inst.client_settings.profiles.push(ClientProfile::default());
+                changed = true;
+            }
+
+            if inst.clients.is_empty() && inst.pending_clients.is_empty() {
+                return;
+            }
+            ui.separator();
+            let mut cancel = None;
+            for (i, pending) in inst.pending_clients.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("{}: waiting", pending.profile.name));
+                    if ui.small_button("Cancel").clicked() {
+                        cancel = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = cancel {
+                inst.pending_clients.remove(i);
+            }
+            let mut close = None;
+            for (i, client) in inst.clients.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    let status = client.status();
+                    ui.colored_label(status.color(), "●");
+                    ui.label(format!("{}: {}", client.name, status));
+                    if client.child.is_some() {
+                        if ui.small_button("Stop").clicked() {
+                            client.stop();
+                        }
+                    } else if ui.small_button("Close").clicked() {
+                        close = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = close {
+                inst.close_client(i);
+            }
+        });
+    changed
+}
+
+fn profile_settings(profile: &mut ClientProfile, ui: &mut egui::Ui, client_env: &EnvList) -> bool {
+    let mut changed = false;
+    egui::Grid::new("client_profile")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Name");
+            changed |= ui.text_edit_singleline(&mut profile.name).changed();
+            ui.end_row();
+
+            ui.label("Command");
+            changed |= ui
+                .add(egui::TextEdit::singleline(&mut profile.command).hint_text("hello_xr"))
+                .changed();
+            ui.end_row();
+
+            ui.label("Arguments");
+            changed |= ui.text_edit_singleline(&mut profile.args).changed();
+            ui.end_row();
+
+            ui.label("Start");
+            changed |= ui
+                .checkbox(&mut profile.with_service, "With monado-service")
+                .changed();
+            ui.end_row();
+
+            ui.label("Wait");
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("client_wait")
+                    .selected_text(profile.wait.to_string())
+                    .show_ui(ui, |ui| {
+                        for wait in ClientWait::ALL {
+                            changed |= ui
+                                .selectable_value(&mut profile.wait, wait, wait.to_string())
+                                .changed();
+                        }
+                    });
+                if profile.wait == ClientWait::Delay {
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut profile.delay)
+                                .clamp_range(0.0..=600.0)
+                                .speed(0.1)
+                                .suffix(" s"),
+                        )
+                        .changed();
+                }
+            });
+            ui.end_row();
+        });
+    ui.label("Environment");
+    changed |= EnvVars::custom_table(&mut profile.env, ui, client_env);
+    if let Some(err) = profile.argv_error() {
+        ui.colored_label(Color32::LIGHT_RED, err);
+    }
+    changed
+}
@@ -1,170 +1,684 @@
-use crate::compositor::WindowType::{
-    Auto, NvidiaDirect, RandrDirect, Vk, Wayland, WaylandDirect, Xcb,
-};
+use crate::env_var::EnvSink;
 use crate::instance::MonadoInstance;
+use crate::vk_display;
 use egui::{Context, Ui, WidgetText};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::ops::RangeInclusive;
-use subprocess::Exec;
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// Compositor tuning beyond forcing a window type, `None` leaves a variable to Monado's default.
+#[derive(Default, Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(default)]
 pub struct CompositorSettings {
-    pub forcing: Forcing,
+    pub scale_percentage: Option<u32>,
+    pub desired_mode: Option<u32>,
+    pub default_framerate: Option<u32>,
+    /// Composite with compute shaders instead of the graphics pipeline.
+    pub compute: Option<bool>,
+    pub null_compositor: Option<bool>,
+    pub debug_gui: Option<bool>,
+    pub vulkan_validation: Option<bool>,
+    /// Where older instance.toml files kept the forcing settings, moved into `WindowType` by
+    /// `migrate_legacy_forcing` and not saved again.
+    #[serde(skip_serializing)]
+    forcing: Option<LegacyForcing>,
 }
 impl CompositorSettings {
     pub fn new() -> Self {
-        CompositorSettings {
-            forcing: Forcing::default(),
+        CompositorSettings::default()
+    }
+
+    pub fn set_vars<C: EnvSink>(&self, mut command: C) -> C {
+        if let Some(scale) = self.scale_percentage {
+            command = command.env("XRT_COMPOSITOR_SCALE_PERCENTAGE", scale.to_string());
+        }
+        if let Some(mode) = self.desired_mode {
+            command = command.env("XRT_COMPOSITOR_DESIRED_MODE", mode.to_string());
+        }
+        if let Some(framerate) = self.default_framerate {
+            command = command.env("XRT_COMPOSITOR_DEFAULT_FRAMERATE", framerate.to_string());
+        }
+        if let Some(compute) = self.compute {
+            command = command.env("XRT_COMPOSITOR_COMPUTE", compute.to_string());
+        }
+        if let Some(null) = self.null_compositor {
+            command = command.env("XRT_COMPOSITOR_NULL", null.to_string());
+        }
+        if let Some(debug_gui) = self.debug_gui {
+            command = command.env("XRT_DEBUG_GUI", debug_gui.to_string());
         }
+        // A loader variable rather than a Monado one, so only setting it does anything.
+        if self.vulkan_validation == Some(true) {
+            command = command.env("VK_INSTANCE_LAYERS", "VK_LAYER_KHRONOS_validation");
+        }
+        command
     }
 
-    pub fn update(inst: &mut MonadoInstance, ctx: &Context) {
+    pub fn update(inst: &mut MonadoInstance, ctx: &Context) -> bool {
         egui::Window::new("Compositor")
             .collapsible(true)
             .show(ctx, |ui| {
-                Forcing::update(inst, ui);
-            });
+                forcing(inst, ui) | inst.compositor_settings.tuning(ui)
+            })
+            .and_then(|r| r.inner)
+            .unwrap_or(false)
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Forcing {
-    pub nvidia_str_enabled: bool,
-    pub nvidia_str: String,
-    pub xcb_fullscreen: bool,
-    pub xcb_screen: u32,
+    fn tuning(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        ui.collapsing("Tuning", |ui| {
+            egui::Grid::new("compositor_tuning")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Scale").on_hover_text(
+                        "XRT_COMPOSITOR_SCALE_PERCENTAGE, the size of the views apps render \
+                         relative to the display, Monado uses 140% by default.",
+                    );
+                    changed |= optional_value(ui, &mut self.scale_percentage, 140, 10..=400, "%");
+                    ui.end_row();
+
+                    ui.label("Desired Mode").on_hover_text(
+                        "XRT_COMPOSITOR_DESIRED_MODE, which of the display's modes direct mode \
+                         uses, unset lets Monado pick one.",
+                    );
+                    changed |= optional_value(ui, &mut self.desired_mode, 0, 0..=63, "");
+                    ui.end_row();
+
+                    ui.label("Default Framerate").on_hover_text(
+                        "XRT_COMPOSITOR_DEFAULT_FRAMERATE, the refresh rate assumed for \
+                         displays that don't report one, such as a window.",
+                    );
+                    changed |= optional_value(ui, &mut self.default_framerate, 60, 1..=240, " Hz");
+                    ui.end_row();
+
+                    ui.label("Pipeline").on_hover_text(
+                        "XRT_COMPOSITOR_COMPUTE, composite with compute shaders instead of the \
+                         graphics pipeline.",
+                    );
+                    changed |= optional_bool(
+                        ui,
+                        "compositor_compute",
+                        &mut self.compute,
+                        "Compute",
+                        "Graphics",
+                    );
+                    ui.end_row();
+
+                    ui.label("Null Compositor").on_hover_text(
+                        "XRT_COMPOSITOR_NULL, run headless without showing anything, for \
+                         testing apps without a display.",
+                    );
+                    changed |= optional_bool(
+                        ui,
+                        "compositor_null",
+                        &mut self.null_compositor,
+                        "On",
+                        "Off",
+                    );
+                    ui.end_row();
+
+                    ui.label("Debug Window").on_hover_text(
+                        "XRT_DEBUG_GUI, open Monado's debug window with its devices, \
+                         trackers and compositor.",
+                    );
+                    changed |=
+                        optional_bool(ui, "compositor_debug_gui", &mut self.debug_gui, "On", "Off");
+                    ui.end_row();
+
+                    ui.label("Vulkan Validation").on_hover_text(
+                        "Loads VK_LAYER_KHRONOS_validation through VK_INSTANCE_LAYERS, the \
+                         Vulkan SDK's validation layers have to be installed.",
+                    );
+                    changed |= optional_bool(
+                        ui,
+                        "compositor_vulkan_validation",
+                        &mut self.vulkan_validation,
+                        "On",
+                        "Off",
+                    );
+                    ui.end_row();
+                });
+        });
+        changed
+    }
 }
-impl Default for Forcing {
-    fn default() -> Self {
-        Forcing {
-            nvidia_str_enabled: false,
-            nvidia_str: "".to_string(),
-            xcb_fullscreen: false,
-            xcb_screen: 0,
+
+/// A checkbox that sets the value, which starts out as `default`, and the value next to it.
+fn optional_value(
+    ui: &mut Ui,
+    value: &mut Option<u32>,
+    default: u32,
+    range: RangeInclusive<u32>,
+    suffix: &str,
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        let mut set = value.is_some();
+        if ui
+            .checkbox(&mut set, "")
+            .on_hover_text("Unset uses Monado's default.")
+            .changed()
+        {
+            *value = set.then_some(default);
+            changed = true;
         }
-    }
+        let mut shown = value.unwrap_or(default);
+        let response = ui.add_enabled(
+            set,
+            egui::DragValue::new(&mut shown)
+                .clamp_range(range)
+                .suffix(suffix),
+        );
+        if set && response.changed() {
+            *value = Some(shown);
+            changed = true;
+        }
+    });
+    changed
 }
-impl Forcing {
-    pub fn update(inst: &mut MonadoInstance, ui: &mut Ui) {
-        ui.collapsing("Forcing", |ui| {
-            ui.radio_value(&mut inst.env_vars.window_type, Auto, "Auto");
-            ui.radio_value(&mut inst.env_vars.window_type, Wayland, "Wayland");
-            ui.radio_value(
-                &mut inst.env_vars.window_type,
-                WaylandDirect,
-                "Wayland Direct",
-            );
-            ui.radio_value(&mut inst.env_vars.window_type, RandrDirect, "Randr Direct");
-            {
-                ui.horizontal_wrapped(|ui| {
-                    let radio = ui.add(egui::RadioButton::new(
-                        matches!(&mut inst.env_vars.window_type, NvidiaDirect(_)),
-                        "Nvidia Direct",
-                    ));
-                    if radio.clicked() {
-                        inst.env_vars.window_type = NvidiaDirect(Some(String::new()));
-                    }
-                    let is_enabled = matches!(&mut inst.env_vars.window_type, NvidiaDirect(_));
-                    ui.add_enabled_ui(is_enabled, |ui| {
-                        ui.checkbox(
-                            &mut inst.compositor_settings.forcing.nvidia_str_enabled,
-                            "Enable Custom Display String",
-                        )
-                    });
-                    let enable_display_str = inst.compositor_settings.forcing.nvidia_str_enabled;
-                    ui.add_enabled_ui(enable_display_str && is_enabled, |ui| {
-                        ui.text_edit_singleline(&mut inst.compositor_settings.forcing.nvidia_str)
-                    });
-                });
+
+/// Unset, or one of two values.
+fn optional_bool(ui: &mut Ui, id: &str, value: &mut Option<bool>, on: &str, off: &str) -> bool {
+    let mut changed = false;
+    let text = |value: Option<bool>| match value {
+        None => "Unset",
+        Some(true) => on,
+        Some(false) => off,
+    };
+    egui::ComboBox::from_id_source(id)
+        .selected_text(text(*value))
+        .show_ui(ui, |ui| {
+            for option in [None, Some(true), Some(false)] {
+                changed |= ui.selectable_value(value, option, text(option)).changed();
             }
-            {
-                let radio = ui
-                    .add(egui::RadioButton::new(
-                        matches!(&mut inst.env_vars.window_type, Xcb(_, _)),
-                        "Xcb",
-                    ))
-                    .on_hover_ui(|ui| {
-                        ui.label(
-                            "Do not use direct mode and run the Monado compositor in a window.",
-                        );
-                    });
-                if radio.clicked() {
-                    inst.env_vars.window_type = Xcb(XcbScreenType::Windowed, XcbScreenNumber(0));
-                }
-                egui::CollapsingHeader::new(WidgetText::default())
-                    .open(Some(matches!(&mut inst.env_vars.window_type, Xcb(_, _))))
-                    .enabled(matches!(&mut inst.env_vars.window_type, Xcb(_, _)))
-                    .show(ui, |ui| {
-                        ui.checkbox(
-                            &mut inst.compositor_settings.forcing.xcb_fullscreen,
-                            "Fullscreen",
-                        );
-                        ui.add(
-                            egui::DragValue::new(&mut inst.compositor_settings.forcing.xcb_screen)
-                                .clamp_range(RangeInclusive::new(0, 10)),
-                        );
-                    });
+        });
+    changed
+}
+
+/// Edits the instance's `WindowType` in place, it is all `set_vars` reads.
+fn forcing(inst: &mut MonadoInstance, ui: &mut Ui) -> bool {
+    let mut changed = false;
+    let mut probe = false;
+    let mut refresh = false;
+    ui.collapsing("Forcing", |ui| {
+        let window_type = &mut inst.env_vars.window_type;
+        let displays = inst.displays.get(ui.ctx());
+        ui.horizontal(|ui| {
+            refresh = ui
+                .add_enabled(displays.is_some(), egui::Button::new("Refresh Displays"))
+                .on_hover_text("Look for connected displays again, likely headsets are marked 🥽.")
+                .clicked();
+            if displays.is_none() {
+                ui.spinner();
+            }
+        });
+        changed |= ui
+            .radio_value(&mut window_type.mode, WindowMode::Auto, "Auto")
+            .changed();
+        changed |= ui
+            .radio_value(&mut window_type.mode, WindowMode::Wayland, "Wayland")
+            .changed();
+        changed |= ui
+            .radio_value(
+                &mut window_type.mode,
+                WindowMode::WaylandDirect,
+                "Wayland Direct",
+            )
+            .changed();
+        if matches!(
+            window_type.mode,
+            WindowMode::Wayland | WindowMode::WaylandDirect
+        ) {
+            if let Some(displays) = displays {
+                displays.wayland_outputs(ui);
             }
+        }
+        changed |= ui
+            .radio_value(
+                &mut window_type.mode,
+                WindowMode::RandrDirect,
+                "Randr Direct",
+            )
+            .changed();
+        ui.horizontal_wrapped(|ui| {
+            changed |= ui
+                .radio_value(
+                    &mut window_type.mode,
+                    WindowMode::NvidiaDirect,
+                    "Nvidia Direct",
+                )
+                .changed();
+            let is_enabled = window_type.mode == WindowMode::NvidiaDirect;
+            changed |= ui
+                .add_enabled_ui(is_enabled, |ui| {
+                    ui.checkbox(
+                        &mut window_type.nvidia_display_enabled,
+                        "Enable Custom Display String",
+                    )
+                })
+                .inner
+                .changed();
+            let enable_display_str = window_type.nvidia_display_enabled;
+            changed |= ui
+                .add_enabled_ui(enable_display_str && is_enabled, |ui| {
+                    ui.text_edit_singleline(&mut window_type.nvidia_display)
+                        .changed()
+                        | displays.is_some_and(|d| d.nvidia_picker(window_type, ui))
+                })
+                .inner;
         });
+        changed |= vk_display::picker(window_type, &mut inst.vk_displays, &mut probe, ui);
+        {
+            changed |= ui
+                .radio_value(&mut window_type.mode, WindowMode::Xcb, "Xcb")
+                .on_hover_ui(|ui| {
+                    ui.label("Do not use direct mode and run the Monado compositor in a window.");
+                })
+                .changed();
+            let is_xcb = window_type.mode == WindowMode::Xcb;
+            egui::CollapsingHeader::new(WidgetText::default())
+                .open(Some(is_xcb))
+                .enabled(is_xcb)
+                .show(ui, |ui| {
+                    changed |= ui
+                        .checkbox(&mut window_type.xcb_fullscreen, "Fullscreen")
+                        .changed();
+                    ui.horizontal(|ui| {
+                        if let Some(displays) = displays {
+                            changed |= displays.xcb_picker(window_type, ui);
+                        }
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut window_type.xcb_screen)
+                                    .clamp_range(RangeInclusive::new(0, 10)),
+                            )
+                            .changed();
+                    });
+                });
+        }
+    });
+    if probe {
+        inst.probe_vk_displays(ui.ctx().clone());
     }
+    if refresh {
+        inst.displays.start(ui.ctx().clone());
+    }
+    changed
 }
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[derive(Default)]
-pub enum WindowType {
-    #[default]
-    Auto,
-    NvidiaDirect(Option<String>),
-    Vk(u32),
-    RandrDirect,
-    WaylandDirect,
-    Xcb(XcbScreenType, XcbScreenNumber),
-    Wayland,
+
+/// How the compositor gets a display, together with the settings of every mode so switching
+/// back and forth doesn't lose them. Only the ones of `mode` are used.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Default)]
+#[serde(default)]
+pub struct WindowType {
+    pub mode: WindowMode,
+    pub nvidia_display_enabled: bool,
+    pub nvidia_display: String,
+    pub vk_display: u32,
+    pub xcb_fullscreen: bool,
+    pub xcb_screen: u32,
 }
 impl WindowType {
-    pub fn set_vars(&self, mut command: Exec) -> Exec {
-        match &self {
-            Auto => {}
-            NvidiaDirect(opt_display_string) => {
+    pub fn set_vars<C: EnvSink>(&self, mut command: C) -> C {
+        match self.mode {
+            WindowMode::Auto => {}
+            WindowMode::NvidiaDirect => {
                 command = command.env("XRT_COMPOSITOR_FORCE_NVIDIA", "true");
-                match opt_display_string {
-                    None => {}
-                    Some(display_str) => {
-                        command = command.env("XRT_COMPOSITOR_FORCE_NVIDIA_DISPLAY", display_str);
-                    }
+                if self.nvidia_display_enabled {
+                    command =
+                        command.env("XRT_COMPOSITOR_FORCE_NVIDIA_DISPLAY", &self.nvidia_display);
                 }
             }
-            Vk(vk) => {
-                command = command.env("XRT_COMPOSITOR_FORCE_VK_DISPLAY", vk.to_string());
+            WindowMode::Vk => {
+                command = command.env(
+                    "XRT_COMPOSITOR_FORCE_VK_DISPLAY",
+                    self.vk_display.to_string(),
+                );
             }
-            RandrDirect => {
+            WindowMode::RandrDirect => {
                 command = command.env("XRT_COMPOSITOR_FORCE_RANDR", "true");
             }
-            WaylandDirect => {
+            WindowMode::WaylandDirect => {
                 command = command.env("XRT_COMPOSITOR_FORCE_WAYLAND_DIRECT", "true");
             }
-            Xcb(screen_type, screen_number) => {
+            WindowMode::Xcb => {
                 command = command.env("XRT_COMPOSITOR_FORCE_XCB", "true");
                 command = command.env(
                     "XRT_COMPOSITOR_XCB_FULLSCREEN",
-                    matches!(screen_type, XcbScreenType::Fullscreen).to_string(),
+                    self.xcb_fullscreen.to_string(),
                 );
-                command = command.env("XRT_COMPOSITOR_XCB_DISPLAY", screen_number.0.to_string());
+                command = command.env("XRT_COMPOSITOR_XCB_DISPLAY", self.xcb_screen.to_string());
             }
-            Wayland => {
+            WindowMode::Wayland => {
                 command = command.env("XRT_COMPOSITOR_FORCE_WAYLAND", "true");
             }
         }
         command
     }
+
+    /// Older instance.toml files saved `WindowMode` as an enum, a plain string for the variants
+    /// without data and a table like `{ Vk = 1 }` for the others.
+    pub fn deserialize_compat<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Saved {
+            Mode(WindowMode),
+            Legacy(LegacyWindowType),
+            WindowType(WindowType),
+        }
+        Ok(match Saved::deserialize(deserializer)? {
+            Saved::Mode(mode) => WindowType {
+                mode,
+                ..WindowType::default()
+            },
+            Saved::Legacy(legacy) => legacy.into(),
+            Saved::WindowType(window_type) => window_type,
+        })
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[derive(Default)]
-pub enum XcbScreenType {
+/// `WindowType` as older instance.toml files saved it.
+#[derive(Debug, Deserialize)]
+enum LegacyWindowType {
+    NvidiaDirect(Option<String>),
+    Vk(u32),
+    Xcb(LegacyXcbScreenType, u32),
+}
+#[derive(Debug, Deserialize)]
+enum LegacyXcbScreenType {
     Fullscreen,
-    #[default]
     Windowed,
 }
+impl From<LegacyWindowType> for WindowType {
+    fn from(legacy: LegacyWindowType) -> Self {
+        match legacy {
+            LegacyWindowType::NvidiaDirect(display) => WindowType {
+                mode: WindowMode::NvidiaDirect,
+                nvidia_display_enabled: display.is_some(),
+                nvidia_display: display.unwrap_or_default(),
+                ..WindowType::default()
+            },
+            LegacyWindowType::Vk(vk_display) => WindowType {
+                mode: WindowMode::Vk,
+                vk_display,
+                ..WindowType::default()
+            },
+            LegacyWindowType::Xcb(screen_type, xcb_screen) => WindowType {
+                mode: WindowMode::Xcb,
+                xcb_fullscreen: matches!(screen_type, LegacyXcbScreenType::Fullscreen),
+                xcb_screen,
+                ..WindowType::default()
+            },
+        }
+    }
+}
+
+/// `[compositor_settings.forcing]` of older instance.toml files, what the Forcing panel edited
+/// before `WindowType` held it.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(default)]
+struct LegacyForcing {
+    nvidia_str_enabled: bool,
+    nvidia_str: String,
+    xcb_fullscreen: bool,
+    xcb_screen: u32,
+}
+
+impl MonadoInstance {
+    /// Moves the forcing settings of an older instance.toml into `WindowType`. The panel's
+    /// settings win over the data the enum carried, only the panel could change them.
+    pub fn migrate_legacy_forcing(&mut self) {
+        let Some(forcing) = self.compositor_settings.forcing.take() else {
+            return;
+        };
+        let window_type = &mut self.env_vars.window_type;
+        if forcing.nvidia_str_enabled || !forcing.nvidia_str.is_empty() {
+            window_type.nvidia_display_enabled = forcing.nvidia_str_enabled;
+            window_type.nvidia_display = forcing.nvidia_str;
+        }
+        if forcing.xcb_fullscreen || forcing.xcb_screen != 0 {
+            window_type.xcb_fullscreen = forcing.xcb_fullscreen;
+            window_type.xcb_screen = forcing.xcb_screen;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum WindowMode {
+    #[default]
+    Auto,
+    NvidiaDirect,
+    Vk,
+    RandrDirect,
+    WaylandDirect,
+    Xcb,
+    Wayland,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_var::{EnvList, EnvVars};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
-pub struct XcbScreenNumber(pub u32);
+    fn env(window_type: &WindowType) -> Vec<(String, String)> {
+        window_type
+            .set_vars(EnvList::default())
+            .0
+            .into_iter()
+            .collect()
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn mode(mode: WindowMode) -> WindowType {
+        WindowType {
+            mode,
+            ..WindowType::default()
+        }
+    }
+
+    #[test]
+    fn env_per_mode() {
+        assert_eq!(env(&mode(WindowMode::Auto)), vars(&[]));
+        assert_eq!(
+            env(&mode(WindowMode::Wayland)),
+            vars(&[("XRT_COMPOSITOR_FORCE_WAYLAND", "true")])
+        );
+        assert_eq!(
+            env(&mode(WindowMode::WaylandDirect)),
+            vars(&[("XRT_COMPOSITOR_FORCE_WAYLAND_DIRECT", "true")])
+        );
+        assert_eq!(
+            env(&mode(WindowMode::RandrDirect)),
+            vars(&[("XRT_COMPOSITOR_FORCE_RANDR", "true")])
+        );
+        assert_eq!(
+            env(&mode(WindowMode::NvidiaDirect)),
+            vars(&[("XRT_COMPOSITOR_FORCE_NVIDIA", "true")])
+        );
+        assert_eq!(
+            env(&WindowType {
+                vk_display: 2,
+                ..mode(WindowMode::Vk)
+            }),
+            vars(&[("XRT_COMPOSITOR_FORCE_VK_DISPLAY", "2")])
+        );
+        assert_eq!(
+            env(&mode(WindowMode::Xcb)),
+            vars(&[
+                ("XRT_COMPOSITOR_FORCE_XCB", "true"),
+                ("XRT_COMPOSITOR_XCB_FULLSCREEN", "false"),
+                ("XRT_COMPOSITOR_XCB_DISPLAY", "0"),
+            ])
+        );
+    }
+
+    #[test]
+    fn env_uses_mode_settings() {
+        let mut window_type = WindowType {
+            nvidia_display: "Valve Corporation Index HMD".to_string(),
+            xcb_fullscreen: true,
+            xcb_screen: 1,
+            ..mode(WindowMode::NvidiaDirect)
+        };
+        // The display string is kept but not used until it is enabled.
+        assert_eq!(
+            env(&window_type),
+            vars(&[("XRT_COMPOSITOR_FORCE_NVIDIA", "true")])
+        );
+        window_type.nvidia_display_enabled = true;
+        assert_eq!(
+            env(&window_type),
+            vars(&[
+                ("XRT_COMPOSITOR_FORCE_NVIDIA", "true"),
+                (
+                    "XRT_COMPOSITOR_FORCE_NVIDIA_DISPLAY",
+                    "Valve Corporation Index HMD"
+                ),
+            ])
+        );
+        window_type.mode = WindowMode::Xcb;
+        assert_eq!(
+            env(&window_type),
+            vars(&[
+                ("XRT_COMPOSITOR_FORCE_XCB", "true"),
+                ("XRT_COMPOSITOR_XCB_FULLSCREEN", "true"),
+                ("XRT_COMPOSITOR_XCB_DISPLAY", "1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips_through_instance_toml() {
+        let mut instance = MonadoInstance::default();
+        instance.env_vars.window_type = WindowType {
+            mode: WindowMode::Xcb,
+            nvidia_display_enabled: true,
+            nvidia_display: "DP-2".to_string(),
+            vk_display: 1,
+            xcb_fullscreen: true,
+            xcb_screen: 3,
+        };
+        let saved = toml::to_string(&instance).unwrap();
+        let loaded: MonadoInstance = toml::from_str(&saved).unwrap();
+        assert_eq!(loaded.env_vars.window_type, instance.env_vars.window_type);
+    }
+
+    #[test]
+    fn tuning_env_and_round_trip() {
+        let unset = CompositorSettings::default();
+        assert_eq!(
+            unset.set_vars(EnvList::default()).0,
+            Vec::<(String, String)>::new()
+        );
+
+        let mut instance = MonadoInstance::default();
+        instance.compositor_settings = CompositorSettings {
+            scale_percentage: Some(100),
+            desired_mode: Some(2),
+            default_framerate: Some(90),
+            compute: Some(false),
+            null_compositor: Some(true),
+            debug_gui: Some(true),
+            vulkan_validation: Some(true),
+            ..CompositorSettings::default()
+        };
+        assert_eq!(
+            instance.compositor_settings.set_vars(EnvList::default()).0,
+            vars(&[
+                ("XRT_COMPOSITOR_SCALE_PERCENTAGE", "100"),
+                ("XRT_COMPOSITOR_DESIRED_MODE", "2"),
+                ("XRT_COMPOSITOR_DEFAULT_FRAMERATE", "90"),
+                ("XRT_COMPOSITOR_COMPUTE", "false"),
+                ("XRT_COMPOSITOR_NULL", "true"),
+                ("XRT_DEBUG_GUI", "true"),
+                ("VK_INSTANCE_LAYERS", "VK_LAYER_KHRONOS_validation"),
+            ])
+        );
+
+        let saved = toml::to_string(&instance).unwrap();
+        let loaded: MonadoInstance = toml::from_str(&saved).unwrap();
+        assert_eq!(loaded.compositor_settings, instance.compositor_settings);
+        // Older files have an empty or forcing-only table.
+        let mut loaded: MonadoInstance = toml::from_str(
+            "[env_vars]\nwindow_type = \"Auto\"\n\n[compositor_settings.forcing]\nxcb_screen = 0\n",
+        )
+        .unwrap();
+        loaded.migrate_legacy_forcing();
+        assert_eq!(loaded.compositor_settings, unset);
+    }
+
+    #[test]
+    fn loads_baseline_instance_toml() {
+        let load = |text: &str| {
+            let mut instance: MonadoInstance = toml::from_str(text).unwrap();
+            instance.migrate_legacy_forcing();
+            assert!(!toml::to_string(&instance).unwrap().contains("forcing"));
+            instance.env_vars.window_type
+        };
+        let forcing = "[compositor_settings.forcing]\n\
+            nvidia_str_enabled = true\n\
+            nvidia_str = \"Valve Corporation Index HMD\"\n\
+            xcb_fullscreen = false\n\
+            xcb_screen = 0\n";
+        let unchanged_forcing = "[compositor_settings.forcing]\n\
+            nvidia_str_enabled = false\n\
+            nvidia_str = \"\"\n\
+            xcb_fullscreen = false\n\
+            xcb_screen = 0\n";
+
+        assert_eq!(
+            load(&format!("[env_vars.window_type]\nVk = 1\n\n{}", forcing)),
+            WindowType {
+                vk_display: 1,
+                nvidia_display_enabled: true,
+                nvidia_display: "Valve Corporation Index HMD".to_string(),
+                ..mode(WindowMode::Vk)
+            }
+        );
+        assert_eq!(
+            load(&format!(
+                "[env_vars.window_type]\nNvidiaDirect = \"\"\n\n{}",
+                forcing
+            )),
+            WindowType {
+                nvidia_display_enabled: true,
+                nvidia_display: "Valve Corporation Index HMD".to_string(),
+                ..mode(WindowMode::NvidiaDirect)
+            }
+        );
+        assert_eq!(
+            load(&format!(
+                "[env_vars]\nwindow_type = {{ NvidiaDirect = \"DP-2\" }}\n\n{}",
+                unchanged_forcing
+            )),
+            WindowType {
+                nvidia_display_enabled: true,
+                nvidia_display: "DP-2".to_string(),
+                ..mode(WindowMode::NvidiaDirect)
+            }
+        );
+        assert_eq!(
+            load(&format!(
+                "[env_vars.window_type]\nXcb = [\"Fullscreen\", 2]\n\n{}",
+                unchanged_forcing
+            )),
+            WindowType {
+                xcb_fullscreen: true,
+                xcb_screen: 2,
+                ..mode(WindowMode::Xcb)
+            }
+        );
+    }
+
+    #[test]
+    fn loads_mode_only_window_type() {
+        let loaded: EnvVars = toml::from_str("window_type = \"WaylandDirect\"\n").unwrap();
+        assert_eq!(loaded.window_type, mode(WindowMode::WaylandDirect));
+    }
+}
@@ -1,85 +1,217 @@
+use crate::child_output::{OutputSender, OUTPUT_CHANNEL_SIZE};
+use crate::instance::MonadoInstance;
+use crate::log_buffer::{console_font, LogBuffer};
+use crate::log_filter::LogFilter;
 use crate::RexApp;
-use ansi_parser::{AnsiParser, AnsiSequence, Output};
-use eframe::epaint::text::TextWrapping;
-use egui::text::LayoutJob;
-use egui::WidgetText::Galley;
-use egui::{Color32, FontFamily, FontId, ScrollArea, TextFormat, Ui};
-use std::default::Default;
+use egui::{Context, Label, ScrollArea, Ui};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::mpsc::{sync_channel, Receiver},
+    time::Duration,
+};
 
-pub fn update(state: &mut RexApp, ui: &mut Ui, frame: &eframe::Frame) {
-    ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
-        if let Ok(input_str) = state.stdout_receiver.try_recv() {
-            state.console.push_str(input_str.as_str());
+/// An instance's output, everything it starts writes into `sender`.
+pub struct Console {
+    pub buffer: LogBuffer,
+    pub filter: LogFilter,
+    sender: OutputSender,
+    receiver: Receiver<String>,
+}
+impl Default for Console {
+    fn default() -> Self {
+        Console::new(ConsoleSettings::default().max_lines, None)
+    }
+}
+impl std::fmt::Debug for Console {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Console")
+            .field("lines", &(self.buffer.end() - self.buffer.start()))
+            .finish_non_exhaustive()
+    }
+}
+impl Console {
+    pub fn new(max_lines: usize, repaint: Option<Context>) -> Self {
+        let (sender, receiver) = sync_channel(OUTPUT_CHANNEL_SIZE);
+        Console {
+            buffer: LogBuffer::new(max_lines),
+            filter: LogFilter::default(),
+            sender: OutputSender::new(sender, repaint),
+            receiver,
+        }
+    }
+
+    /// An empty console with the same capacity that wakes up the same UI.
+    pub fn sibling(&self) -> Console {
+        Console::new(self.buffer.capacity(), self.sender.repaint_context())
+    }
+
+    pub fn sender(&self) -> OutputSender {
+        self.sender.clone()
+    }
+
+    /// Moves waiting output into the buffer.
+    pub fn drain(&mut self) {
+        // Bounded so a child that outputs faster than this frame drains can't keep it going forever.
+        for input_str in self.receiver.try_iter().take(OUTPUT_CHANNEL_SIZE) {
+            self.buffer.push_str(input_str.as_str());
         }
+    }
+
+    /// Takes the waiting output instead of moving it into the buffer, for printing it. Waits up
+    /// to `timeout` for some to arrive.
+    pub fn take_output(&self, timeout: Duration) -> Vec<String> {
+        let mut output: Vec<String> = self.receiver.recv_timeout(timeout).into_iter().collect();
+        output.extend(self.receiver.try_iter().take(OUTPUT_CHANNEL_SIZE));
+        output
+    }
 
-        let mut text_layout_job = LayoutJob::default();
-        text_layout_job.wrap = TextWrapping {
-            max_width: frame.info().window_info.size.x,
-            ..Default::default()
-        };
-        let mut output = None;
-        state.console.ansi_parse().for_each(|a| match a {
-            Output::TextBlock(text_block) => text_layout_job.append(
-                text_block,
-                0.0,
-                match &output {
-                    None => TextFormat {
-                        font_id: FontId::new(14.0, FontFamily::Monospace),
-                        color: Color32::GRAY,
-                        ..Default::default()
-                    },
-                    Some(AnsiSequence::SetGraphicsMode(graphics)) => {
-                        let mut color = Color32::GRAY;
-                        let _my_vec = graphics.to_vec();
+    pub fn is_empty(&self) -> bool {
+        self.buffer.start() == self.buffer.end()
+    }
+}
+
+pub fn update(state: &mut RexApp, ui: &mut Ui) {
+    tabs(state, ui);
+    let Some(instance) = state.current_instance() else {
+        ui.centered_and_justified(|ui| ui.label("Select or create an instance to see its output."));
+        return;
+    };
+    view_tabs(instance, ui);
+    let console = instance.shown_console_mut();
 
-                        let graphics_color = graphics.get(1);
+    console.filter.update(ui);
+    console.filter.sync(&console.buffer);
+
+    // Every line is a single unwrapped row, so only the visible ones need laying out.
+    let row_height = ui.fonts(|f| f.row_height(&console_font()));
+    let scroll_to_row = console.filter.scroll_to_row.take();
+    // Sticking to the bottom would override the jump to a search match.
+    let mut scroll_area = ScrollArea::both()
+        .stick_to_bottom(scroll_to_row.is_none())
+        .auto_shrink([false; 2]);
+    if let Some(row) = scroll_to_row {
+        let row_height_with_spacing = row_height + ui.spacing().item_spacing.y;
+        scroll_area = scroll_area.vertical_scroll_offset(row as f32 * row_height_with_spacing);
+    }
+    let filter = &console.filter;
+    scroll_area.show_rows(ui, row_height, filter.visible_len(), |ui, row_range| {
+        for row in row_range {
+            let Some(index) = filter.visible_line(row) else {
+                continue;
+            };
+            if let Some(line) = console.buffer.get(index) {
+                let job = filter.highlight(line, filter.is_current_hit(index));
+                ui.add(Label::new(job).wrap(false));
+            }
+        }
+    });
+}
 
-                        if graphics_color.is_some() {
-                            match graphics_color.unwrap(){
-                                31 => {
-                                    color = Color32::RED;
-                                }
-                                32 => {
-                                    color = Color32::GREEN;
-                                }
-                                33 => {
-                                    color = Color32::YELLOW;
-                                }
-                                34 => {
-                                    color = Color32::BLUE;
-                                }
-                                35 => {
-                                    color = Color32::from_rgb(255, 0, 255);
-                                }
-                                36 => {
-                                    color = Color32::from_rgb(0, 255, 255);
-                                }
-                                37 => {
-                                    color = Color32::from_rgb(255, 255, 255);
-                                }
-                                &_ => {}
-                            }
-                        } else {
-                            color = Color32::GRAY;
-                        }
-                        TextFormat {
-                            font_id: FontId::new(14.0, FontFamily::Monospace),
-                            color,
-                            ..Default::default()
-                        }
-                    }
-                    Some(_) => TextFormat {
-                        font_id: FontId::new(14.0, FontFamily::Monospace),
-                        color: Color32::GRAY,
-                        ..Default::default()
-                    },
-                },
-            ),
-            Output::Escape(ansi) => {
-                output = Some(ansi);
+/// One tab per instance that is running or has output, picking one selects the instance.
+fn tabs(state: &mut RexApp, ui: &mut Ui) {
+    let mut names: Vec<&String> = state
+        .instances
+        .iter()
+        .filter(|(name, i)| {
+            i.child.is_some()
+                || !i.console.is_empty()
+                || state.current_instance.as_ref() == Some(name)
+        })
+        .map(|(name, _)| name)
+        .collect();
+    if names.len() < 2 {
+        return;
+    }
+    names.sort();
+    let mut selected = None;
+    ui.horizontal(|ui| {
+        for name in names {
+            let status = state.instances[name].status();
+            let current = state.current_instance.as_ref() == Some(name);
+            ui.colored_label(status.color(), "●")
+                .on_hover_text(status.to_string());
+            if ui.selectable_label(current, name).clicked() {
+                selected = Some(name.clone());
             }
-        });
-        let galley = ui.fonts(|f| f.layout_job(text_layout_job));
-        ui.label(Galley(galley));
+        }
     });
+    ui.separator();
+    if selected.is_some() {
+        state.current_instance = selected;
+    }
+}
+
+/// monado-service, every client launched for the instance and an opened session log get a tab
+/// of their own.
+fn view_tabs(instance: &mut MonadoInstance, ui: &mut Ui) {
+    if instance.clients.is_empty() && instance.opened_log.is_none() {
+        return;
+    }
+    let mut close_log = false;
+    ui.horizontal(|ui| {
+        let status = instance.status();
+        ui.colored_label(status.color(), "●")
+            .on_hover_text(status.to_string());
+        ui.selectable_value(
+            &mut instance.shown_console,
+            ConsoleView::Service,
+            "monado-service",
+        );
+        for (i, client) in instance.clients.iter().enumerate() {
+            let status = client.status();
+            ui.colored_label(status.color(), "●")
+                .on_hover_text(status.to_string());
+            ui.selectable_value(
+                &mut instance.shown_console,
+                ConsoleView::Client(i),
+                &client.name,
+            );
+        }
+        if let Some((name, _)) = &instance.opened_log {
+            ui.label("📄");
+            ui.selectable_value(&mut instance.shown_console, ConsoleView::SessionLog, name);
+            close_log = ui.small_button("✖").on_hover_text("Close").clicked();
+        }
+    });
+    ui.separator();
+    if close_log {
+        instance.opened_log = None;
+        if instance.shown_console == ConsoleView::SessionLog {
+            instance.shown_console = ConsoleView::Service;
+        }
+    }
+}
+
+/// Which of an instance's consoles is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleView {
+    /// monado-service's output, also used for build steps.
+    #[default]
+    Service,
+    Client(usize),
+    /// `MonadoInstance::opened_log`.
+    SessionLog,
+}
+
+impl MonadoInstance {
+    /// The console `shown_console` picks, monado-service's if that one is gone.
+    pub fn shown_console_mut(&mut self) -> &mut Console {
+        match self.shown_console {
+            ConsoleView::Client(i) if i < self.clients.len() => &mut self.clients[i].console,
+            ConsoleView::SessionLog if self.opened_log.is_some() => {
+                &mut self.opened_log.as_mut().unwrap().1
+            }
+            _ => &mut self.console,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct ConsoleSettings {
+    pub max_lines: usize,
+}
+impl Default for ConsoleSettings {
+    fn default() -> Self {
+        ConsoleSettings { max_lines: 100_000 }
+    }
 }
@@ -1,9 +1,10 @@
+use crate::service::ServiceStatus;
 use crate::RexApp;
-use ansi_parser::{AnsiParser, Output};
 use egui::panel::TopBottomSide;
-use egui::{Color32, ComboBox, Context, Layout, Ui, Widget};
+use egui::{Color32, ComboBox, Context, DragValue, Layout, Ui, Widget};
 
 pub fn update(state: &mut RexApp, ctx: &Context) {
+    notifications(state, ctx);
     egui::TopBottomPanel::new(TopBottomSide::Bottom, "control_panel").show(ctx, |ui| {
         ui.horizontal(|ui| {
             monado_control_buttons(state, ui);
@@ -17,7 +18,10 @@ fn monado_control_buttons(state: &mut RexApp, ui: &mut Ui) {
     let current_instance = state.current_instance();
     let instance_active = current_instance.is_some();
     let monado_active = instance_active && current_instance.as_ref().unwrap().child.is_some();
-    let start_button = ui.add_enabled_ui(instance_active, |ui| {
+    let monado_stopping = monado_active && current_instance.as_ref().unwrap().is_stopping();
+    let monado_restarting = instance_active
+        && current_instance.as_ref().unwrap().status() == ServiceStatus::Restarting;
+    let start_button = ui.add_enabled_ui(instance_active && !monado_stopping, |ui| {
         if !monado_active {
             egui::Button::new(" Start ").fill(Color32::from_rgb(0, 40, 0))
         } else {
@@ -25,11 +29,32 @@ fn monado_control_buttons(state: &mut RexApp, ui: &mut Ui) {
         }
         .ui(ui)
     });
-    let stop_button = ui.add_enabled_ui(monado_active, |ui| {
+    let stop_button = ui.add_enabled_ui((monado_active && !monado_stopping) || monado_restarting, |ui| {
         egui::Button::new("Stop")
             .fill(Color32::from_rgb(40, 0, 0))
             .ui(ui)
     });
+    if let Some(instance) = state.current_instance() {
+        let status = instance.status();
+        if matches!(
+            status,
+            ServiceStatus::Starting | ServiceStatus::Stopping | ServiceStatus::Restarting
+        ) {
+            ui.spinner();
+        } else {
+            ui.colored_label(status.color(), "●");
+        }
+        let label = ui.colored_label(status.color(), status.to_string());
+        if status == ServiceStatus::Starting {
+            label.on_hover_text(instance.waiting_for());
+        } else if let Some((remaining, reason)) = instance.pending_restart() {
+            label.on_hover_text(format!(
+                "Restarting in {:.1}s after: {}",
+                remaining.as_secs_f32(),
+                reason
+            ));
+        }
+    }
 
     if state.current_instance().is_none() {
         return;
@@ -37,19 +62,93 @@ fn monado_control_buttons(state: &mut RexApp, ui: &mut Ui) {
 
     if start_button.inner.clicked() {
         let logging_env_vars = state.logging_env_vars;
-        let stdout_sender = state.stdout_sender.clone();
-        state.console.clear();
         let Some(instance) = state.current_instance() else {return};
-        let _ = instance.kill_monado();
-        instance.start_monado(&logging_env_vars, stdout_sender);
+        instance.clear_console = true;
+        if monado_active {
+            // Started again by `poll_monado` once the old one is gone.
+            instance.restart_after_stop = true;
+            let _ = instance.stop_monado();
+        } else {
+            let stdout_sender = instance.console.sender();
+            if let Err(err) = instance.start_monado(&logging_env_vars, stdout_sender) {
+                instance.start_error = Some(err);
+            }
+        }
     }
     if stop_button.inner.clicked() {
         let Some(instance) = state.current_instance() else {return};
-        let _ = instance.kill_monado();
+        let _ = instance.stop_monado();
     }
 }
 
+/// Failed starts and exits rex didn't ask for, per instance until dismissed.
+fn notifications(state: &mut RexApp, ctx: &Context) {
+    let mut names: Vec<String> = state
+        .instances
+        .iter()
+        .filter(|(_, i)| {
+            i.unexpected_exit.is_some() || i.start_error.is_some() || i.stale_socket.is_some()
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    if names.is_empty() {
+        return;
+    }
+    names.sort();
+
+    egui::TopBottomPanel::top("notifications").show(ctx, |ui| {
+        for name in names {
+            let Some(instance) = state.instances.get_mut(&name) else {continue};
+            if let Some(err) = instance.start_error.as_ref().map(ToString::to_string) {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::LIGHT_RED,
+                        format!("⚠ Unable to start monado-service of '{}': {}", name, err),
+                    );
+                    if ui.small_button("Dismiss").clicked() {
+                        instance.start_error = None;
+                    }
+                });
+            }
+            if let Some(status) = instance.unexpected_exit {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        status.color(),
+                        format!("⚠ monado-service of '{}': {}", name, status),
+                    );
+                    if ui.small_button("Dismiss").clicked() {
+                        instance.unexpected_exit = None;
+                    }
+                });
+            }
+            if let Some(socket) = instance.stale_socket.clone() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        format!(
+                            "⚠ '{}' has a stale IPC socket left by a monado-service that is gone: {}",
+                            name,
+                            socket.display()
+                        ),
+                    );
+                    if ui.small_button("Remove").clicked() {
+                        match std::fs::remove_file(&socket) {
+                            Ok(()) => instance.stale_socket = None,
+                            Err(err) => println!("Unable to remove {}: {}", socket.display(), err),
+                        }
+                    }
+                    if ui.small_button("Dismiss").clicked() {
+                        instance.stale_socket = None;
+                    }
+                });
+            }
+        }
+    });
+}
+
 fn instance_selector(state: &mut RexApp, ui: &mut Ui) {
+    let mut names: Vec<String> = state.instances.keys().cloned().collect();
+    names.sort();
     ComboBox::from_id_source(0)
         .selected_text(
             state
@@ -57,27 +156,49 @@ fn instance_selector(state: &mut RexApp, ui: &mut Ui) {
                 .unwrap_or("Select Instance"),
         )
         .show_ui(ui, |ui| {
-            for name in state.instances.keys() {
-                ui.selectable_value(&mut state.current_instance, Some(name.clone()), name);
+            for name in names {
+                let status = state.instances[&name].status();
+                ui.horizontal(|ui| {
+                    ui.colored_label(status.color(), "●")
+                        .on_hover_text(status.to_string());
+                    ui.selectable_value(&mut state.current_instance, Some(name.clone()), &name);
+                });
             }
         });
 }
 
 fn log_buttons(state: &mut RexApp, ui: &mut Ui) {
     ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
-        if ui.button("Clear").clicked() {
-            state.console.clear();
+        if let Some(instance) = state.current_instance() {
+            let console = instance.shown_console_mut();
+            if ui.button("Clear").clicked() {
+                console.buffer.clear();
+            }
+            if ui.button("Copy To Clipboard").clicked() {
+                let output_string = console.buffer.plain_text();
+                ui.output_mut(|o| o.copied_text = output_string);
+            };
         }
-        if ui.button("Copy To Clipboard").clicked() {
-            let mut output_string = String::new();
-            state.console.ansi_parse().for_each(|ansi| match ansi {
-                Output::TextBlock(block) => {
-                    output_string.push_str(block);
+        let max_lines = ui
+            .add(
+                DragValue::new(&mut state.console_settings.max_lines)
+                    .clamp_range(1_000..=10_000_000)
+                    .speed(1_000)
+                    .suffix(" lines"),
+            )
+            .on_hover_text("Oldest lines are dropped once the log is longer than this.");
+        if max_lines.changed() {
+            let max_lines = state.console_settings.max_lines;
+            for instance in state.instances.values_mut() {
+                instance.console.buffer.set_capacity(max_lines);
+                for client in &mut instance.clients {
+                    client.console.buffer.set_capacity(max_lines);
                 }
-                Output::Escape(_) => {}
-            });
-            ui.output_mut(|o| o.copied_text = output_string);
-        };
+            }
+        }
+        if max_lines.drag_released() || (max_lines.changed() && !max_lines.dragged()) {
+            state.save_global();
+        }
         ui.label("Log:");
     });
 }
@@ -0,0 +1,567 @@
+use crate::compositor::WindowType;
+use egui::{Color32, ComboBox, Context, RichText, Ui};
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    thread,
+};
+use subprocess::{Exec, Redirection};
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const EDID_BLOCK: usize = 128;
+/// Microsoft's vendor specific data block, headsets use it to say they are no desktop monitor.
+const MICROSOFT_OUI: [u8; 3] = [0x5c, 0x12, 0xca];
+
+/// Headsets that don't say so in their EDID, after the kernel's non-desktop quirks. `None`
+/// matches every product of the vendor.
+const HMD_QUIRKS: [(&str, Option<u16>); 15] = [
+    ("HVR", None),
+    ("OVR", None),
+    ("PVR", None),
+    ("SEN", None),
+    ("SVR", None),
+    ("VLV", None),
+    ("SNY", Some(0x0704)),
+    ("ACR", Some(0x7fce)),
+    ("AUS", Some(0xc102)),
+    ("DEL", Some(0x7fce)),
+    ("FUJ", Some(0x1970)),
+    ("HPN", Some(0x3515)),
+    ("LEN", Some(0x0408)),
+    ("LEN", Some(0xb800)),
+    ("SEC", Some(0x144a)),
+];
+
+/// The Nvidia driver names displays after the manufacturer, which EDID only has as a PNP id.
+const MANUFACTURER_NAMES: [(&str, &str); 4] = [
+    ("HVR", "HTC Corporation"),
+    ("OVR", "Oculus VR Inc."),
+    ("SNY", "Sony"),
+    ("VLV", "Valve Corporation"),
+];
+
+/// The parts of a display's EDID that help telling displays apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// Three letter PNP id, e.g. `VLV`.
+    pub vendor: String,
+    pub product: u16,
+    /// The monitor name descriptor.
+    pub name: Option<String>,
+    /// Of the preferred timing.
+    pub resolution: Option<(u32, u32)>,
+    /// Marked as not meant for the desktop in a Microsoft vendor block.
+    pub non_desktop: bool,
+}
+impl Edid {
+    /// Parses the base block and CTA-861 extensions, `None` if `bytes` isn't an EDID.
+    pub fn parse(bytes: &[u8]) -> Option<Edid> {
+        let base = bytes.get(..EDID_BLOCK)?;
+        if base[..8] != EDID_HEADER {
+            return None;
+        }
+        let id = u16::from_be_bytes([base[8], base[9]]);
+        let vendor = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+            .collect();
+        let product = u16::from_le_bytes([base[10], base[11]]);
+
+        let mut name = None;
+        let mut resolution = None;
+        for descriptor in base[54..126].chunks_exact(18) {
+            if descriptor[0] != 0 || descriptor[1] != 0 {
+                // The first detailed timing is the preferred one.
+                if resolution.is_none() {
+                    let width = descriptor[2] as u32 | ((descriptor[4] as u32 & 0xf0) << 4);
+                    let height = descriptor[5] as u32 | ((descriptor[7] as u32 & 0xf0) << 4);
+                    resolution = Some((width, height));
+                }
+            } else if descriptor[3] == 0xfc {
+                let text = String::from_utf8_lossy(&descriptor[5..]);
+                let text = text.split('\n').next().unwrap_or_default().trim();
+                if !text.is_empty() {
+                    name = Some(text.to_string());
+                }
+            }
+        }
+
+        let non_desktop = bytes[EDID_BLOCK..]
+            .chunks_exact(EDID_BLOCK)
+            .take(base[126] as usize)
+            .any(microsoft_non_desktop);
+
+        Some(Edid {
+            vendor,
+            product,
+            name,
+            resolution,
+            non_desktop,
+        })
+    }
+
+    /// The monitor name, or vendor and product code for displays without one.
+    pub fn model(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{} {:04x}", self.vendor, self.product),
+        }
+    }
+
+    /// Either says it isn't a desktop monitor or is a headset known to leave that out.
+    pub fn is_hmd(&self) -> bool {
+        self.non_desktop
+            || HMD_QUIRKS.iter().any(|(vendor, product)| {
+                *vendor == self.vendor
+                    && match product {
+                        Some(product) => *product == self.product,
+                        None => true,
+                    }
+            })
+    }
+
+    /// How the Nvidia driver is likely to name this display, its prefix is what
+    /// `XRT_COMPOSITOR_FORCE_NVIDIA_DISPLAY` is matched against.
+    pub fn nvidia_name(&self) -> String {
+        match MANUFACTURER_NAMES.iter().find(|(id, _)| *id == self.vendor) {
+            Some((_, manufacturer)) => format!("{} {}", manufacturer, self.model()),
+            None => self.model(),
+        }
+    }
+}
+
+/// Whether a CTA-861 extension block has a Microsoft vendor block without desktop usage.
+fn microsoft_non_desktop(block: &[u8]) -> bool {
+    if block[0] != 0x02 {
+        return false;
+    }
+    let end = (block[2] as usize).clamp(4, EDID_BLOCK - 1);
+    let mut offset = 4;
+    while offset < end {
+        let tag = block[offset] >> 5;
+        let len = (block[offset] & 0x1f) as usize;
+        let Some(payload) = block.get(offset + 1..offset + 1 + len) else {
+            return false;
+        };
+        if tag == 3 && len >= 5 && payload[..3] == MICROSOFT_OUI {
+            let version = payload[3];
+            let desktop_usage = payload[4] & 0x40 != 0;
+            return version == 1 || version == 2 || (version == 3 && !desktop_usage);
+        }
+        offset += 1 + len;
+    }
+    false
+}
+
+/// A connected connector of a DRM card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrmOutput {
+    /// Connector name like `DP-1`, the same X and wlroots use on most drivers.
+    pub connector: String,
+    pub edid: Option<Edid>,
+}
+impl DrmOutput {
+    pub fn is_hmd(&self) -> bool {
+        self.edid.as_ref().is_some_and(Edid::is_hmd)
+    }
+}
+impl fmt::Display for DrmOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.connector)?;
+        if let Some(edid) = &self.edid {
+            write!(f, ": {}", edid.model())?;
+            if let Some((width, height)) = edid.resolution {
+                write!(f, " ({}x{})", width, height)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every connected output in `/sys/class/drm`, sorted by connector.
+pub fn drm_outputs(sys_class_drm: &Path) -> Vec<DrmOutput> {
+    let mut outputs: Vec<DrmOutput> = fs::read_dir(sys_class_drm)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_name = entry.file_name().to_str()?.to_string();
+            // `card0-DP-1`, skipping the cards themselves and render nodes.
+            let (card, connector) = file_name.split_once('-')?;
+            if !card.starts_with("card") {
+                return None;
+            }
+            let status = fs::read_to_string(entry.path().join("status")).ok()?;
+            if status.trim() != "connected" {
+                return None;
+            }
+            let edid = fs::read(entry.path().join("edid"))
+                .ok()
+                .and_then(|bytes| Edid::parse(&bytes));
+            Some(DrmOutput {
+                connector: connector.to_string(),
+                edid,
+            })
+        })
+        .collect();
+    outputs.sort_by(|a, b| a.connector.cmp(&b.connector));
+    outputs
+}
+
+/// An X output that is showing something, numbered like `XRT_COMPOSITOR_XCB_DISPLAY` expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XOutput {
+    pub index: u32,
+    pub name: String,
+    pub resolution: (u32, u32),
+}
+
+/// The connected outputs with a mode set in `xrandr --query` output, in the order Monado counts
+/// them.
+pub fn parse_xrandr(output: &str) -> Vec<XOutput> {
+    let mut outputs = Vec::new();
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (Some(name), Some("connected")) = (words.next(), words.next()) else {
+            continue;
+        };
+        // `2560x1440+0+0`, outputs without one are connected but off.
+        let geometry = words.find_map(|word| {
+            let (size, _) = word.split_once('+')?;
+            let (width, height) = size.split_once('x')?;
+            Some((width.parse().ok()?, height.parse().ok()?))
+        });
+        if let Some(resolution) = geometry {
+            outputs.push(XOutput {
+                index: outputs.len() as u32,
+                name: name.to_string(),
+                resolution,
+            });
+        }
+    }
+    outputs
+}
+
+/// An output of a wlroots based Wayland compositor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaylandOutput {
+    pub name: String,
+    pub description: String,
+}
+
+/// The `DP-1 "Dell Inc. DELL U2719D (DP-1)"` lines of `wlr-randr` output.
+pub fn parse_wlr_randr(output: &str) -> Vec<WaylandOutput> {
+    output
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with(char::is_whitespace))
+        .map(|line| match line.split_once(' ') {
+            Some((name, description)) => WaylandOutput {
+                name: name.to_string(),
+                description: description.trim().trim_matches('"').to_string(),
+            },
+            None => WaylandOutput {
+                name: line.to_string(),
+                description: String::new(),
+            },
+        })
+        .collect()
+}
+
+/// `program`'s output, `None` if it isn't installed or fails.
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let capture = Exec::cmd(program)
+        .args(args)
+        .stdin(Redirection::None)
+        .stdout(Redirection::Pipe)
+        .stderr(Redirection::None)
+        .capture()
+        .ok()?;
+    capture.success().then(|| capture.stdout_str())
+}
+
+/// The Forcing panel's displays, xrandr and wlr-randr are run on a thread of their own.
+#[derive(Debug, Default)]
+pub enum DisplaysLoad {
+    #[default]
+    NotLoaded,
+    Loading(Receiver<Displays>),
+    Loaded(Displays),
+}
+impl DisplaysLoad {
+    /// Looks for connected displays again, the UI is repainted once they are found.
+    pub fn start(&mut self, ctx: Context) {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(Displays::load());
+            ctx.request_repaint();
+        });
+        *self = DisplaysLoad::Loading(receiver);
+    }
+
+    /// The loaded displays, `None` while they are being looked for the first time or again.
+    pub fn get(&mut self, ctx: &Context) -> Option<&Displays> {
+        match self {
+            DisplaysLoad::NotLoaded => self.start(ctx.clone()),
+            DisplaysLoad::Loading(receiver) => match receiver.try_recv() {
+                Ok(displays) => *self = DisplaysLoad::Loaded(displays),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    *self = DisplaysLoad::Loaded(Displays::default())
+                }
+            },
+            DisplaysLoad::Loaded(_) => {}
+        }
+        match self {
+            DisplaysLoad::Loaded(displays) => Some(displays),
+            _ => None,
+        }
+    }
+}
+
+/// What rex could find out about the displays attached to this machine.
+#[derive(Debug, Default)]
+pub struct Displays {
+    pub drm: Vec<DrmOutput>,
+    /// `None` without an X server to ask.
+    pub x: Option<Vec<XOutput>>,
+    /// `None` without a Wayland compositor that `wlr-randr` can ask.
+    pub wayland: Option<Vec<WaylandOutput>>,
+}
+impl Displays {
+    pub fn load() -> Displays {
+        let x = std::env::var_os("DISPLAY")
+            .and_then(|_| run("xrandr", &["--query"]))
+            .map(|output| parse_xrandr(&output));
+        let wayland = std::env::var_os("WAYLAND_DISPLAY")
+            .and_then(|_| run("wlr-randr", &[]))
+            .map(|output| parse_wlr_randr(&output));
+        Displays {
+            drm: drm_outputs(Path::new("/sys/class/drm")),
+            x,
+            wayland,
+        }
+    }
+
+    /// Whether the DRM connector an X or Wayland output is named after looks like a headset.
+    pub fn is_hmd(&self, connector: &str) -> bool {
+        self.drm
+            .iter()
+            .any(|output| output.connector == connector && output.is_hmd())
+    }
+
+    fn label(&self, text: String, connector: &str) -> RichText {
+        if self.is_hmd(connector) {
+            RichText::new(format!("🥽 {}", text)).color(Color32::LIGHT_GREEN)
+        } else {
+            RichText::new(text)
+        }
+    }
+
+    /// Fills the Nvidia display string from a connected display.
+    pub fn nvidia_picker(&self, window_type: &mut WindowType, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        let outputs: Vec<(&DrmOutput, &Edid)> = self
+            .drm
+            .iter()
+            .filter_map(|output| Some((output, output.edid.as_ref()?)))
+            .collect();
+        if outputs.is_empty() {
+            return false;
+        }
+        ComboBox::from_id_source("nvidia_display")
+            .selected_text("Connected Displays")
+            .show_ui(ui, |ui| {
+                for (output, edid) in outputs {
+                    let name = edid.nvidia_name();
+                    let selected = window_type.nvidia_display == name;
+                    if ui
+                        .selectable_label(
+                            selected,
+                            self.label(output.to_string(), &output.connector),
+                        )
+                        .on_hover_text(&name)
+                        .clicked()
+                    {
+                        window_type.nvidia_display = name;
+                        changed = true;
+                    }
+                }
+            })
+            .response
+            .on_hover_text(
+                "The driver names displays after their manufacturer and model, \
+                 monado-service uses the first one whose name starts with this string.",
+            );
+        changed
+    }
+
+    /// Picks the X output Xcb mode opens its window on.
+    pub fn xcb_picker(&self, window_type: &mut WindowType, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        let Some(outputs) = self.x.as_ref().filter(|outputs| !outputs.is_empty()) else {
+            return false;
+        };
+        let text = |output: &XOutput| {
+            format!(
+                "{}: {} ({}x{})",
+                output.index, output.name, output.resolution.0, output.resolution.1
+            )
+        };
+        let selected = outputs
+            .iter()
+            .find(|output| output.index == window_type.xcb_screen)
+            .map_or_else(|| format!("{}: Not found", window_type.xcb_screen), text);
+        ComboBox::from_id_source("xcb_screen")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for output in outputs {
+                    changed |= ui
+                        .selectable_value(
+                            &mut window_type.xcb_screen,
+                            output.index,
+                            self.label(text(output), &output.name),
+                        )
+                        .changed();
+                }
+            });
+        changed
+    }
+
+    /// The Wayland outputs, nothing picks one but it shows which of them is the headset.
+    pub fn wayland_outputs(&self, ui: &mut Ui) {
+        let Some(outputs) = &self.wayland else {
+            return;
+        };
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Wayland Outputs:");
+            for output in outputs {
+                ui.label(self.label(output.name.clone(), &output.name))
+                    .on_hover_text(&output.description);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALVE_INDEX: &[u8] = include_bytes!("../tests/fixtures/valve-index.edid");
+    const DELL_U2719D: &[u8] = include_bytes!("../tests/fixtures/dell-u2719d.edid");
+
+    #[test]
+    fn parses_edid() {
+        let index = Edid::parse(VALVE_INDEX).unwrap();
+        assert_eq!(
+            index,
+            Edid {
+                vendor: "VLV".to_string(),
+                product: 0x91a8,
+                name: Some("Index HMD".to_string()),
+                resolution: Some((2880, 1600)),
+                non_desktop: true,
+            }
+        );
+        assert!(index.is_hmd());
+        assert_eq!(index.nvidia_name(), "Valve Corporation Index HMD");
+
+        let dell = Edid::parse(DELL_U2719D).unwrap();
+        assert_eq!(
+            dell,
+            Edid {
+                vendor: "DEL".to_string(),
+                product: 0xa0e2,
+                name: Some("DELL U2719D".to_string()),
+                resolution: Some((2560, 1440)),
+                non_desktop: false,
+            }
+        );
+        assert!(!dell.is_hmd());
+        assert_eq!(dell.nvidia_name(), "DELL U2719D");
+
+        assert_eq!(Edid::parse(&DELL_U2719D[..100]), None);
+        assert_eq!(Edid::parse(&[0; 128]), None);
+    }
+
+    #[test]
+    fn reads_connected_drm_outputs() {
+        let dir = std::env::temp_dir().join(format!("rex-drm-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, status, edid) in [
+            ("card0-DP-1", "connected", DELL_U2719D),
+            ("card0-DP-2", "connected\n", VALVE_INDEX),
+            ("card0-HDMI-A-1", "disconnected", &[][..]),
+            ("renderD128", "connected", &[][..]),
+        ] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("status"), status).unwrap();
+            fs::write(dir.join(name).join("edid"), edid).unwrap();
+        }
+
+        let outputs = drm_outputs(&dir);
+        let connectors: Vec<&str> = outputs.iter().map(|o| o.connector.as_str()).collect();
+        assert_eq!(connectors, ["DP-1", "DP-2"]);
+        assert!(!outputs[0].is_hmd());
+        assert!(outputs[1].is_hmd());
+        assert_eq!(outputs[1].to_string(), "DP-2: Index HMD (2880x1600)");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parses_xrandr_and_wlr_randr() {
+        let xrandr = "Screen 0: minimum 320 x 200, current 5120 x 1440, maximum 16384 x 16384\n\
+            DP-1 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm\n   \
+               2560x1440     59.95*+\n\
+            DP-2 connected (normal left inverted right x axis y axis)\n   \
+               2880x1600     90.00 +\n\
+            HDMI-A-1 disconnected (normal left inverted right x axis y axis)\n\
+            DP-3 connected 2560x1440+2560+0 (normal left inverted right x axis y axis) 597mm x 336mm\n";
+        assert_eq!(
+            parse_xrandr(xrandr),
+            vec![
+                XOutput {
+                    index: 0,
+                    name: "DP-1".to_string(),
+                    resolution: (2560, 1440),
+                },
+                XOutput {
+                    index: 1,
+                    name: "DP-3".to_string(),
+                    resolution: (2560, 1440),
+                },
+            ]
+        );
+
+        let wlr_randr = "DP-1 \"Dell Inc. DELL U2719D 1234 (DP-1)\"\n  \
+              Enabled: yes\n  \
+              Modes:\n    \
+                2560x1440 px, 59.951000 Hz (preferred, current)\n";
+        assert_eq!(
+            parse_wlr_randr(wlr_randr),
+            vec![WaylandOutput {
+                name: "DP-1".to_string(),
+                description: "Dell Inc. DELL U2719D 1234 (DP-1)".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn loads_in_the_background() {
+        let ctx = Context::default();
+        let mut displays = DisplaysLoad::default();
+        assert!(displays.get(&ctx).is_none());
+        assert!(matches!(displays, DisplaysLoad::Loading(_)));
+        for _ in 0..250 {
+            if displays.get(&ctx).is_some() {
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        panic!("displays were not loaded within 5s");
+    }
+}
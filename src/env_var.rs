@@ -1,14 +1,183 @@
-use crate::compositor::WindowType;
+use crate::{compositor::WindowType, instance::MonadoInstance, log_options::LoggingEnvVars};
+use egui::{Color32, Context, Ui};
 use serde::{Deserialize, Serialize};
 use subprocess::Exec;
 
+/// Something environment variables can be set on, so the same `set_vars` code
+/// can configure a command or just list what it would set.
+pub trait EnvSink: Sized {
+    fn env(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self;
+}
+impl EnvSink for Exec {
+    fn env(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        Exec::env(self, key.as_ref(), value.as_ref())
+    }
+}
+
+/// Ordered list of the variables a command would receive. Setting a key twice keeps the last value.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct EnvList(pub Vec<(String, String)>);
+impl EnvSink for EnvList {
+    fn env(mut self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        let key = key.as_ref();
+        let value = value.as_ref().to_string();
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key.to_string(), value)),
+        }
+        self
+    }
+}
+impl EnvList {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct EnvVars {
+    // Ahead of `window_type`, toml can't write an empty list after a table.
+    #[serde(default)]
+    pub custom: Vec<CustomEnvVar>,
+    #[serde(deserialize_with = "WindowType::deserialize_compat")]
     pub window_type: WindowType,
 }
 impl EnvVars {
-    pub fn set_vars(&self, mut command: Exec) -> Exec {
+    /// Applies the typed settings, then the enabled custom variables on top of them.
+    pub fn set_vars<C: EnvSink>(&self, command: C) -> C {
+        self.set_custom_vars(self.set_typed_vars(command))
+    }
+
+    pub fn set_custom_vars<C: EnvSink>(&self, mut command: C) -> C {
+        for var in self.custom.iter().filter(|v| v.is_active()) {
+            command = command.env(var.key.trim(), &var.value);
+        }
+        command
+    }
+
+    pub fn set_typed_vars<C: EnvSink>(&self, mut command: C) -> C {
         command = self.window_type.set_vars(command);
         command
     }
+
+    pub fn update(
+        inst: &mut MonadoInstance,
+        ctx: &Context,
+        logging_env_vars: &LoggingEnvVars,
+    ) -> bool {
+        let mut changed = false;
+        let rex_env = inst.set_rex_vars(logging_env_vars, EnvList::default());
+        egui::Window::new("Environment")
+            .default_open(false)
+            .collapsible(true)
+            .show(ctx, |ui| {
+                changed = EnvVars::custom_table(&mut inst.env_vars.custom, ui, &rex_env);
+                ui.collapsing("Effective Environment", |ui| {
+                    for (key, value) in inst.effective_env(logging_env_vars).0 {
+                        ui.monospace(format!("{}={}", key, value));
+                    }
+                });
+            });
+        changed
+    }
+
+    pub fn custom_table(custom: &mut Vec<CustomEnvVar>, ui: &mut Ui, rex_env: &EnvList) -> bool {
+        let mut changed = false;
+        let mut remove = None;
+        egui::Grid::new("custom_env_vars")
+            .num_columns(5)
+            .show(ui, |ui| {
+                for (i, var) in custom.iter_mut().enumerate() {
+                    changed |= ui
+                        .checkbox(&mut var.enabled, "")
+                        .on_hover_text("Enabled")
+                        .changed();
+                    changed |= ui
+                        .add(egui::TextEdit::singleline(&mut var.key).hint_text("NAME"))
+                        .changed();
+                    changed |= ui
+                        .add(egui::TextEdit::singleline(&mut var.value).hint_text("value"))
+                        .changed();
+                    match rex_env.get(var.key.trim()) {
+                        Some(rex_value) if var.is_active() => {
+                            ui.colored_label(Color32::YELLOW, "⚠")
+                                .on_hover_text(format!(
+                                    "Overrides the value rex sets: {}",
+                                    rex_value
+                                ));
+                        }
+                        _ => {
+                            ui.label("");
+                        }
+                    }
+                    if ui.button("🗑").on_hover_text("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = remove {
+            custom.remove(i);
+            changed = true;
+        }
+        if ui.button("Add Variable").clicked() {
+            custom.push(CustomEnvVar::default());
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CustomEnvVar {
+    pub enabled: bool,
+    pub key: String,
+    pub value: String,
+}
+impl Default for CustomEnvVar {
+    fn default() -> Self {
+        CustomEnvVar {
+            enabled: true,
+            key: String::new(),
+            value: String::new(),
+        }
+    }
+}
+impl CustomEnvVar {
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.key.trim().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_vars_override_rex_vars() {
+        let mut instance = MonadoInstance::default();
+        instance.compositor_settings.scale_percentage = Some(140);
+        instance.env_vars.custom = vec![
+            CustomEnvVar {
+                enabled: true,
+                key: " XRT_COMPOSITOR_SCALE_PERCENTAGE ".to_string(),
+                value: "100".to_string(),
+            },
+            CustomEnvVar {
+                enabled: false,
+                key: "XRT_DEBUG_GUI".to_string(),
+                value: "1".to_string(),
+            },
+        ];
+        let logging = LoggingEnvVars::default();
+
+        let rex_env = instance.set_rex_vars(&logging, EnvList::default());
+        assert_eq!(rex_env.get("XRT_COMPOSITOR_SCALE_PERCENTAGE"), Some("140"));
+        let env = instance.effective_env(&logging);
+        assert_eq!(env.get("XRT_COMPOSITOR_SCALE_PERCENTAGE"), Some("100"));
+        assert_eq!(env.get("XRT_DEBUG_GUI"), None);
+    }
 }
@@ -1,21 +1,42 @@
 use egui::Context;
-use expect_dialog::ExpectDialog;
-use libc::pid_t;
-use nix::{
-    sys::wait::{WaitPidFlag, WaitStatus},
-    unistd::Pid,
-};
+use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
 use std::{
-    io::{BufRead, BufReader, ErrorKind},
-    path::PathBuf,
-    sync::{mpsc::SyncSender, Arc, Mutex},
+    ffi::OsString,
+    fs::DirBuilder,
+    io::ErrorKind,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
+    time::{Duration, Instant},
 };
-use subprocess::{Exec, Popen, Redirection};
+use subprocess::{unix::PopenExt, Exec, ExitStatus, Popen, Redirection};
 
 use crate::{
-    compositor::CompositorSettings, env_var::EnvVars, log_options::LoggingEnvVars, RexApp,
+    ansi,
+    builder::{BuildSettings, BuildStep},
+    child_output::{forward_lines, OutputSender},
+    clients::{self, Client, ClientSettings, PendingClient},
+    compositor::CompositorSettings,
+    console::{Console, ConsoleView},
+    displays::DisplaysLoad,
+    env_var::{EnvList, EnvSink, EnvVars},
+    log_options::LoggingEnvVars,
+    monado_config::{self, ConfigEditor, MonadoConfigSettings},
+    openxr_runtime,
+    service::{
+        find_executable, RestartPlan, ServiceExecutable, ServicePreview, ServiceSettings,
+        ServiceStatus, StartError,
+    },
+    session_log::{self, SessionLog},
+    vk_display::VkDisplayProbe,
 };
 
 #[derive(Default, Debug, Deserialize, Serialize)]
@@ -24,104 +45,673 @@ pub struct MonadoInstance {
     instance_dir: PathBuf,
     pub env_vars: EnvVars,
     pub compositor_settings: CompositorSettings,
+    #[serde(default)]
+    pub build_settings: BuildSettings,
+    #[serde(default)]
+    pub service_settings: ServiceSettings,
+    #[serde(default)]
+    pub client_settings: ClientSettings,
+    #[serde(default)]
+    pub monado_config: MonadoConfigSettings,
+    #[serde(skip)]
+    pub console: Console,
+    /// A past session loaded from disk, with the name it was saved under.
+    #[serde(skip)]
+    pub opened_log: Option<(String, Console)>,
+    #[serde(skip)]
+    pub clients: Vec<Client>,
+    #[serde(skip)]
+    pub pending_clients: Vec<PendingClient>,
+    #[serde(skip)]
+    pub shown_console: ConsoleView,
     #[serde(skip)]
     pub child: Option<Popen>,
+    /// Where the running monado-service's output is saved, shared with the output reader.
+    #[serde(skip)]
+    session_log: Option<SessionLog>,
+    /// Set by the output reader once `child` has printed something.
+    #[serde(skip)]
+    output_seen: Arc<AtomicBool>,
+    /// Set by the output reader once a line matched the ready pattern.
+    #[serde(skip)]
+    pattern_seen: Arc<AtomicBool>,
+    /// Inode of an IPC socket that was already there when `child` started, it doesn't count.
+    #[serde(skip)]
+    old_socket: Option<u64>,
+    /// Latched by `poll_ready` once monado-service accepts clients.
+    #[serde(skip)]
+    ready: bool,
+    /// An IPC socket left behind by a monado-service that is gone, shown until it is dismissed.
+    #[serde(skip)]
+    pub stale_socket: Option<PathBuf>,
+    /// How the last monado-service run ended.
+    #[serde(skip)]
+    last_exit: Option<ServiceStatus>,
+    /// An exit rex didn't ask for, shown until it is dismissed.
+    #[serde(skip)]
+    pub unexpected_exit: Option<ServiceStatus>,
+    /// Why the last start failed, shown until it is dismissed.
+    #[serde(skip)]
+    pub start_error: Option<StartError>,
+    /// When the stop signal was sent to `child`, and whether it has been killed since.
+    #[serde(skip)]
+    stopping: Option<(Instant, bool)>,
+    /// Start monado-service again once the running one has stopped.
+    #[serde(skip)]
+    pub restart_after_stop: bool,
+    /// Clear the console once the next monado-service has been spawned.
+    #[serde(skip)]
+    pub clear_console: bool,
+    /// When the restart policy starts monado-service again, and the exit it does so after.
+    #[serde(skip)]
+    pending_restart: Option<(Instant, ServiceStatus)>,
+    /// When the restart policy started monado-service, for limiting restarts per window.
+    #[serde(skip)]
+    restarts: Vec<Instant>,
+    #[serde(skip)]
+    pub build_process: Option<(BuildStep, Popen)>,
+    /// The displays monado-service can force with `WindowMode::Vk`, found by a probe run.
+    #[serde(skip)]
+    pub vk_displays: VkDisplayProbe,
+    #[serde(skip)]
+    pub service_preview: Option<ServicePreview>,
+    /// Connected displays for the Forcing panel, loaded when it is first shown.
+    #[serde(skip)]
+    pub displays: DisplaysLoad,
+    /// The config_v0.json open in the Monado Config window.
+    #[serde(skip)]
+    pub config_editor: Option<ConfigEditor>,
 }
 impl MonadoInstance {
-    pub fn create_load(app: &RexApp, name: String) -> Result<Self, confy::ConfyError> {
-        let instance_dir = app.monado_instance_dir.join(name);
+    pub fn create_load(monado_instance_dir: &Path, name: String) -> Result<Self, confy::ConfyError> {
+        let instance_dir = monado_instance_dir.join(name);
         let mut instance: MonadoInstance =
             confy::load_path(instance_dir.join("instance.toml"))?;
         instance.instance_dir = instance_dir;
+        instance.migrate_legacy_forcing();
         Ok(instance)
     }
-    
-    pub fn update(&mut self, ctx: &Context) {
-        CompositorSettings::update(self, ctx);
+
+    pub fn save(&self) -> Result<(), confy::ConfyError> {
+        confy::store_path(self.instance_dir.join("instance.toml"), self)
+    }
+
+    pub fn source_dir(&self) -> PathBuf {
+        self.instance_dir.join("source")
+    }
+
+    pub fn build_dir(&self) -> PathBuf {
+        self.instance_dir.join("build")
+    }
+
+    pub fn install_dir(&self) -> PathBuf {
+        self.instance_dir.join("install")
+    }
+
+    pub fn built_monado_service(&self) -> PathBuf {
+        self.install_dir().join("bin").join("monado-service")
+    }
+
+    /// `XDG_CONFIG_HOME` of an instance with its own Monado config, see `monado_config`.
+    pub fn config_dir(&self) -> PathBuf {
+        self.instance_dir.join("config")
+    }
+
+    /// Every monado-service run is saved here, see `session_log`.
+    pub fn logs_dir(&self) -> PathBuf {
+        self.instance_dir.join("logs")
+    }
+
+    /// Written while monado-service runs so other rex processes can find and stop it.
+    pub fn pid_file(&self) -> PathBuf {
+        self.instance_dir.join("monado-service.pid")
+    }
+
+    /// Exists while a monado-service started by any rex process is ready for clients.
+    pub fn ready_file(&self) -> PathBuf {
+        self.instance_dir.join("monado-service.ready")
+    }
+
+    /// PID of a monado-service started for this instance by any rex process, if it is still alive.
+    pub fn running_pid(&self) -> Option<Pid> {
+        let pid = std::fs::read_to_string(self.pid_file()).ok()?;
+        let pid = Pid::from_raw(pid.trim().parse().ok()?);
+        nix::sys::signal::kill(pid, None).ok()?;
+        Some(pid)
+    }
+
+    pub fn name(&self) -> String {
+        self.instance_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Used as monado-service's XDG_RUNTIME_DIR so every instance gets its own IPC socket.
+    pub fn runtime_dir(&self) -> Option<PathBuf> {
+        if !self.service_settings.separate_runtime_dir {
+            return None;
+        }
+        Some(dirs::runtime_dir()?.join("rex").join(self.name()))
+    }
+
+    /// Where monado-service creates the socket clients connect to.
+    pub fn ipc_socket(&self) -> Option<PathBuf> {
+        let runtime_dir = self.runtime_dir().or_else(dirs::runtime_dir)?;
+        Some(runtime_dir.join("monado_comp_ipc"))
+    }
+
+    /// The IPC socket of a monado-service that is gone, nothing accepts connections on it anymore.
+    pub fn find_stale_socket(&self) -> Option<PathBuf> {
+        if self.child.is_some() || self.running_pid().is_some() {
+            return None;
+        }
+        let socket = self.ipc_socket()?;
+        if !std::fs::symlink_metadata(&socket).ok()?.file_type().is_socket() {
+            return None;
+        }
+        match UnixStream::connect(&socket) {
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => Some(socket),
+            _ => None,
+        }
+    }
+
+    pub fn set_runtime_vars<C: EnvSink>(&self, mut command: C) -> C {
+        let Some(runtime_dir) = self.runtime_dir() else {
+            return command;
+        };
+        // Wayland resolves a relative WAYLAND_DISPLAY against XDG_RUNTIME_DIR, keep pointing at the session's.
+        if let (Some(system_dir), Ok(display)) =
+            (dirs::runtime_dir(), std::env::var("WAYLAND_DISPLAY"))
+        {
+            if Path::new(&display).is_relative() {
+                command = command.env("WAYLAND_DISPLAY", system_dir.join(display).to_string_lossy());
+            }
+        }
+        command.env("XDG_RUNTIME_DIR", runtime_dir.to_string_lossy())
+    }
+
+    /// Applies every variable rex sets for monado-service, later ones override earlier ones.
+    pub fn set_vars<C: EnvSink>(&self, logging_env_vars: &LoggingEnvVars, command: C) -> C {
+        // The custom variables come last, they override everything else.
+        self.env_vars.set_vars(self.set_settings_vars(logging_env_vars, command))
+    }
+
+    /// `set_vars` without the custom variables, what those would override.
+    pub fn set_rex_vars<C: EnvSink>(&self, logging_env_vars: &LoggingEnvVars, command: C) -> C {
+        self.env_vars.set_typed_vars(self.set_settings_vars(logging_env_vars, command))
+    }
+
+    /// The variables of every settings window but Environment.
+    fn set_settings_vars<C: EnvSink>(&self, logging_env_vars: &LoggingEnvVars, mut command: C) -> C {
+        command = self.set_runtime_vars(command);
+        command = self.set_config_vars(command);
+        command = logging_env_vars.set_vars(command);
+        command = self.compositor_settings.set_vars(command);
+        command
+    }
+
+    /// Every variable rex sets for monado-service, in the order they are applied.
+    pub fn effective_env(&self, logging_env_vars: &LoggingEnvVars) -> EnvList {
+        self.set_vars(logging_env_vars, EnvList::default())
+    }
+
+    /// The monado-service `service_settings` picks, not yet looked up on PATH.
+    pub fn monado_service(&self) -> PathBuf {
+        let built = self.built_monado_service();
+        match self.service_settings.executable {
+            ServiceExecutable::Automatic if built.is_file() => built,
+            ServiceExecutable::Automatic | ServiceExecutable::System => {
+                PathBuf::from("monado-service")
+            }
+            ServiceExecutable::InstanceBuild => built,
+            ServiceExecutable::Custom => PathBuf::from(self.service_settings.custom_executable.trim()),
+        }
+    }
+
+    /// The full command line monado-service is started with, wrapper included.
+    pub fn service_argv(&self) -> Result<Vec<OsString>, StartError> {
+        let settings = &self.service_settings;
+        let wrapper = shell_words::split(&settings.wrapper)
+            .map_err(|err| StartError::InvalidSettings(format!("Invalid wrapper: {}", err)))?;
+        let args = shell_words::split(&settings.args)
+            .map_err(|err| StartError::InvalidSettings(format!("Invalid arguments: {}", err)))?;
+
+        let mut argv = Vec::new();
+        if let Some((program, wrapper_args)) = wrapper.split_first() {
+            let program = find_executable(Path::new(program))
+                .map_err(|err| StartError::Wrapper(Box::new(err)))?;
+            argv.push(program.into_os_string());
+            argv.extend(wrapper_args.iter().map(OsString::from));
+        }
+        argv.push(find_executable(&self.monado_service())?.into_os_string());
+        argv.extend(args.into_iter().map(OsString::from));
+        Ok(argv)
+    }
+
+    /// Checks the settings and builds the command monado-service is started with from `service_argv`.
+    pub fn service_command(
+        &self,
+        argv: &[OsString],
+        logging_env_vars: &LoggingEnvVars,
+    ) -> Result<Exec, StartError> {
+        let mut command = Exec::cmd(&argv[0]).args(&argv[1..]);
+        let working_dir = self.service_settings.working_dir.trim();
+        if !working_dir.is_empty() {
+            if !Path::new(working_dir).is_dir() {
+                return Err(StartError::InvalidSettings(format!(
+                    "Working directory {} does not exist.",
+                    working_dir
+                )));
+            }
+            command = command.cwd(working_dir);
+        }
+        command = self.set_vars(logging_env_vars, command);
+        Ok(command
+            .stderr(Redirection::Merge)
+            .stdout(Redirection::Pipe)
+            .stdin(Redirection::None))
+    }
+
+    pub fn update(&mut self, ctx: &Context, logging_env_vars: &LoggingEnvVars) {
+        let stdout_sender = &self.console.sender();
+        self.poll_build_step(stdout_sender);
+
+        let mut changed = CompositorSettings::update(self, ctx);
+        changed |= EnvVars::update(self, ctx, logging_env_vars);
+        changed |= BuildSettings::update(self, ctx, stdout_sender);
+        changed |= ServiceSettings::update(self, ctx);
+        changed |= clients::update(self, ctx);
+        changed |= monado_config::update(self, ctx);
+        openxr_runtime::update(self, ctx);
+        session_log::update(self, ctx);
+        if changed {
+            if let Err(err) = self.save() {
+                println!("Error saving instance config: {}", err);
+            }
+        }
+    }
+
+    pub fn start_build_step(
+        &mut self,
+        step: BuildStep,
+        mut stdout_sender: OutputSender,
+    ) -> Result<(), StartError> {
+        let command = step
+            .command(self)?
+            .stderr(Redirection::Merge)
+            .stdout(Redirection::Pipe)
+            .stdin(Redirection::None);
+        stdout_sender.send(format!("> {}\n", command.to_cmdline_lossy()));
+
+        let mut child = command
+            .popen()
+            .map_err(|err| StartError::from_popen(Path::new(step.program()), err))?;
+        if let Some(stdout) = child.stdout.take() {
+            thread::spawn(move || forward_lines(stdout, |line| stdout_sender.send(line)));
+        }
+        self.build_process.replace((step, child));
+        Ok(())
+    }
+
+    /// Reaps a finished build step and reports how it exited.
+    pub fn poll_build_step(&mut self, stdout_sender: &OutputSender) {
+        let Some((step, child)) = self.build_process.as_mut() else { return };
+        let Some(status) = child.poll() else { return };
+        let message = match status {
+            ExitStatus::Exited(0) => format!("{} finished successfully.\n", step),
+            ExitStatus::Exited(code) => format!("{} failed with exit code {}.\n", step, code),
+            ExitStatus::Signaled(signal) => format!("{} was killed by signal {}.\n", step, signal),
+            _ => format!("{} exited.\n", step),
+        };
+        stdout_sender.clone().send(message);
+        self.build_process = None;
     }
 
+    /// Starts monado-service, a failure is also written to the console.
     pub fn start_monado(
         &mut self,
         logging_env_vars: &LoggingEnvVars,
-        stdout_sender: Arc<Mutex<SyncSender<String>>>,
-    ) {
-        let mut command = Exec::cmd("monado-service");
-        command = logging_env_vars.set_vars(command);
-        command = self.env_vars.set_vars(command);
-        command = command.stderr(Redirection::Merge);
-        command = command.stdout(Redirection::Pipe);
-        command = command.stdin(Redirection::None);
-        let mut child;
+        stdout_sender: OutputSender,
+    ) -> Result<(), StartError> {
+        let result = self.spawn_monado(logging_env_vars, stdout_sender.clone());
+        if let Err(err) = &result {
+            stdout_sender.clone().send(format!("Unable to start monado-service: {}\n", err));
+        }
+        result
+    }
 
-        match command.popen() {
-            Ok(popen) => child = popen,
-            Err(err) => panic!("Unable to create monado service: {}", err)
+    fn spawn_monado(
+        &mut self,
+        logging_env_vars: &LoggingEnvVars,
+        mut stdout_sender: OutputSender,
+    ) -> Result<(), StartError> {
+        let clear_console = std::mem::take(&mut self.clear_console);
+        if let Some(runtime_dir) = self.runtime_dir() {
+            if let Err(err) = DirBuilder::new().recursive(true).mode(0o700).create(&runtime_dir) {
+                println!("Unable to create runtime dir {}: {}", runtime_dir.display(), err);
+            }
         }
+        // monado-service can't create its socket while the old one is there, point it out.
+        self.stale_socket = self.find_stale_socket();
+        let argv = self.service_argv()?;
+        let command = self.service_command(&argv, logging_env_vars)?;
+        let ready_regex = self.service_settings.ready_regex()?;
+        let session_log = self.create_session_log(logging_env_vars, &command);
+        let mut output_log = session_log.as_ref().and_then(|log| log.try_clone().ok());
+        let wrapped = !self.service_settings.wrapper.trim().is_empty();
+        let mut child = command.popen().map_err(|err| {
+            let err = StartError::from_popen(Path::new(&argv[0]), err);
+            if wrapped {
+                StartError::Wrapper(Box::new(err))
+            } else {
+                err
+            }
+        })?;
 
-        let pid = child.pid().expect_dialog("Newly created monado service process does not have pid.");
-        let stdout = child.stdout.take().expect_dialog("Monado service process lacks readable stdout.");
+        let (Some(pid), Some(stdout)) = (child.pid(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(StartError::PipeSetupFailed("monado-service has no pid or readable output."));
+        };
+        if clear_console {
+            // Whatever the old one wrote is still waiting in the channel.
+            self.console.drain();
+            self.console.buffer.clear();
+        }
+        let output_seen = Arc::new(AtomicBool::new(false));
+        self.output_seen = output_seen.clone();
+        let pattern_seen = Arc::new(AtomicBool::new(false));
+        self.pattern_seen = pattern_seen.clone();
         thread::spawn(move || {
-            let child_pid = pid;
-            let sender = stdout_sender
-                .lock()
-                .expect("Did the monado GUI crash? Failed to get stdout sender lock. Exiting monado service")
-                .clone();
-            loop {
-                match nix::sys::wait::waitpid(
-                    Pid::from_raw(child_pid as pid_t),
-                    Some(WaitPidFlag::WNOHANG),
-                )
-                {
-                    Ok(WaitStatus::StillAlive) => {}
-                    _ => {
-                        println!("Monado is dead. Quitting monado service");
-                        return;
+            forward_lines(stdout, |line| {
+                output_seen.store(true, Ordering::Relaxed);
+                if let Some(ready_regex) = &ready_regex {
+                    if !pattern_seen.load(Ordering::Relaxed)
+                        && ready_regex.is_match(&ansi::strip(&line))
+                    {
+                        pattern_seen.store(true, Ordering::Relaxed);
                     }
                 }
-                match stdout.try_clone() {
-                    Ok(b) => {
-                        let mut reader = BufReader::new(b);
-                        let mut my_string = String::new();
-                        match reader.read_line(&mut my_string) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                return;
-                            }
-                        }
-                        // Don't know why this needs to be here, just added it on a hunch and now shit works again, idfk lmao
-                        if my_string.is_empty() {
-                            continue;
-                        }
-                        match sender.send(my_string) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                return;
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        return;
-                    }
+                if let Some(output_log) = &mut output_log {
+                    output_log.write_line(&line);
                 }
-            }
+                stdout_sender.send(line)
+            });
+            // The pipe only closes once monado exits, `poll_monado` reaps it and reports how.
+            println!("Monado is dead. Quitting monado service: {}", pid);
         });
 
+        if let Err(err) = std::fs::write(self.pid_file(), pid.to_string()) {
+            println!("Unable to write monado service pid file: {}", err);
+        }
+        self.old_socket = self
+            .ipc_socket()
+            .and_then(|socket| std::fs::symlink_metadata(socket).ok())
+            .map(|metadata| metadata.ino());
+        self.ready = false;
+        self.pending_restart = None;
+        self.session_log = session_log;
+        self.last_exit = None;
+        self.unexpected_exit = None;
+        self.start_error = None;
         self.child.replace(child);
+        self.launch_service_clients();
+        Ok(())
+    }
+
+    /// Starts a new session log with what monado-service is run with, after pruning old ones.
+    fn create_session_log(
+        &self,
+        logging_env_vars: &LoggingEnvVars,
+        command: &Exec,
+    ) -> Option<SessionLog> {
+        let keep = self.service_settings.keep_logs as usize;
+        if keep == 0 {
+            return None;
+        }
+        // Make room for the new one.
+        session_log::prune(&self.logs_dir(), keep - 1);
+
+        let mut header = format!(
+            "rex session log of instance '{}'\ncommand: {}\n",
+            self.name(),
+            command.to_cmdline_lossy()
+        );
+        let working_dir = self.service_settings.working_dir.trim();
+        if !working_dir.is_empty() {
+            header += &format!("working dir: {}\n", working_dir);
+        }
+        header += "environment:\n";
+        for (key, value) in self.effective_env(logging_env_vars).0 {
+            header += &format!("  {}={}\n", key, value);
+        }
+        match SessionLog::create(&self.logs_dir(), &header) {
+            Ok(log) => Some(log),
+            Err(err) => {
+                println!("Unable to create session log: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Whether monado-service, a build step or a client rex started is still around.
+    pub fn has_children(&self) -> bool {
+        self.child.is_some()
+            || self.build_process.is_some()
+            || self.clients.iter().any(|c| c.child.is_some())
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.is_some()
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        if self.child.is_none() && self.pending_restart.is_some() {
+            ServiceStatus::Restarting
+        } else if self.child.is_none() {
+            self.last_exit.unwrap_or(ServiceStatus::NotStarted)
+        } else if self.stopping.is_some() {
+            ServiceStatus::Stopping
+        } else if self.ready {
+            ServiceStatus::Running
+        } else {
+            ServiceStatus::Starting
+        }
+    }
+
+    /// Whether every enabled ready check has passed, without any the first output counts.
+    fn check_ready(&self) -> bool {
+        let settings = &self.service_settings;
+        let socket = if settings.ready_socket {
+            self.ipc_socket()
+        } else {
+            None
+        };
+        let pattern = !settings.ready_pattern.trim().is_empty();
+        if socket.is_none() && !pattern {
+            return self.output_seen.load(Ordering::Relaxed);
+        }
+        let socket_ready = match socket {
+            Some(socket) => std::fs::symlink_metadata(socket).is_ok_and(|metadata| {
+                metadata.file_type().is_socket() && Some(metadata.ino()) != self.old_socket
+            }),
+            None => true,
+        };
+        socket_ready && (!pattern || self.pattern_seen.load(Ordering::Relaxed))
+    }
+
+    /// What a starting monado-service is still waiting for before clients can connect.
+    pub fn waiting_for(&self) -> String {
+        let settings = &self.service_settings;
+        let socket = settings.ready_socket && self.ipc_socket().is_some();
+        let pattern = !settings.ready_pattern.trim().is_empty();
+        match (socket, pattern) {
+            (false, false) => "Waiting for output.".to_string(),
+            (true, false) => "Waiting for the IPC socket.".to_string(),
+            (false, true) => format!("Waiting for a line matching '{}'.", settings.ready_pattern.trim()),
+            (true, true) => format!(
+                "Waiting for the IPC socket and a line matching '{}'.",
+                settings.ready_pattern.trim()
+            ),
+        }
     }
 
-    pub fn kill_monado(&mut self) -> std::io::Result<()> {
-        let Some(mut child) = self.child.take() else {return Err(ErrorKind::BrokenPipe.into())};
-        if let Some(pid) = child.pid() {
-            println!("Killing monado service: {}", pid);
+    /// Checks whether a starting monado-service has become ready, true only when it just did.
+    pub fn poll_ready(&mut self) -> bool {
+        if self.ready || self.child.is_none() || self.stopping.is_some() || !self.check_ready() {
+            return false;
         }
-        else {
-            println!("Killing monado service: [PID NOT AVAILABLE]");
+        self.ready = true;
+        if let Some(log) = &mut self.session_log {
+            log.write_line("monado-service is ready.");
         }
+        if let Err(err) = std::fs::write(self.ready_file(), "") {
+            println!("Unable to write monado service ready file: {}", err);
+        }
+        true
+    }
+
+    /// Forgets the running process once it has been reaped and remembers how it ended.
+    pub fn finish_monado(&mut self, status: ExitStatus) -> ServiceStatus {
+        let status = ServiceStatus::from_exit_status(status);
+        if self.stopping.is_none() && status.is_abnormal() {
+            self.unexpected_exit = Some(status);
+        }
+        self.last_exit = Some(status);
+        if let Some(mut log) = self.session_log.take() {
+            log.write_line(&format!("monado-service: {}.", status));
+        }
+        self.child = None;
+        self.stopping = None;
+        self.ready = false;
+        // Nothing left to wait for, a restart queues them again.
+        self.pending_clients.clear();
+        let _ = std::fs::remove_file(self.pid_file());
+        let _ = std::fs::remove_file(self.ready_file());
+        if status.is_abnormal() {
+            self.stale_socket = self.find_stale_socket();
+        }
+        status
+    }
 
-        child.kill()?;
-        let _ = nix::sys::wait::wait();
-        //We don't need this because we wait in the thread.
-        //nix::sys::wait::wait().unwrap();
+    /// Asks monado-service to shut down with the configured signal, `poll_monado` kills it
+    /// if it is still running once the stop timeout has passed.
+    pub fn stop_monado(&mut self) -> std::io::Result<()> {
+        let Some(child) = self.child.as_mut() else {
+            // Stopping while waiting to restart just cancels the restart.
+            return match self.pending_restart.take() {
+                Some(_) => Ok(()),
+                None => Err(ErrorKind::BrokenPipe.into()),
+            };
+        };
+        if self.stopping.is_some() {
+            return Ok(());
+        }
+        let signal = self.service_settings.stop_signal.signal();
+        match child.pid() {
+            Some(pid) => println!("Stopping monado service with {}: {}", signal, pid),
+            None => println!("Stopping monado service with {}: [PID NOT AVAILABLE]", signal),
+        }
+        child.send_signal(signal as i32)?;
+        self.stopping = Some((Instant::now(), false));
+        if self.client_settings.stop_with_service {
+            self.stop_clients();
+        }
         Ok(())
     }
+
+    /// Reaps monado-service once it has exited, escalating a stop to SIGKILL after the timeout.
+    pub fn poll_monado(&mut self, logging_env_vars: &LoggingEnvVars) -> Option<ExitStatus> {
+        let stdout_sender = self.console.sender();
+        let send = |message: String| {
+            stdout_sender.clone().send(message);
+        };
+        self.poll_restart(logging_env_vars, &stdout_sender);
+        if self.poll_ready() {
+            send("monado-service is ready.\n".to_string());
+        }
+        let child = self.child.as_mut()?;
+        let Some(status) = child.poll() else {
+            if let Some((since, killed)) = self.stopping.as_mut() {
+                let timeout = self.service_settings.stop_timeout();
+                if !*killed && since.elapsed() >= timeout {
+                    send(format!(
+                        "monado-service did not stop within {:.1}s, killing it.\n",
+                        timeout.as_secs_f32()
+                    ));
+                    let _ = child.kill();
+                    *killed = true;
+                }
+            }
+            return None;
+        };
+
+        let requested = self.stopping.is_some();
+        let service_status = self.finish_monado(status);
+        send(format!("monado-service: {}.\n", service_status));
+
+        if std::mem::take(&mut self.restart_after_stop) {
+            if let Err(err) = self.start_monado(logging_env_vars, stdout_sender) {
+                self.start_error = Some(err);
+            }
+        } else if !requested {
+            self.schedule_restart(service_status, &stdout_sender);
+        }
+        Some(status)
+    }
+
+    /// How long until the restart policy starts monado-service again, and the exit it restarts after.
+    pub fn pending_restart(&self) -> Option<(Duration, ServiceStatus)> {
+        let (at, reason) = self.pending_restart?;
+        Some((at.saturating_duration_since(Instant::now()), reason))
+    }
+
+    /// Queues a restart after an exit rex didn't ask for, if the restart policy wants one.
+    fn schedule_restart(&mut self, status: ServiceStatus, stdout_sender: &OutputSender) {
+        let settings = &self.service_settings;
+        let now = Instant::now();
+        let plan = settings.plan_restart(status, &self.restarts, now);
+        let window = settings.restart_window();
+        self.restarts.retain(|&at| now.saturating_duration_since(at) < window);
+        let mut stdout_sender = stdout_sender.clone();
+        match plan {
+            RestartPlan::Stay => {}
+            RestartPlan::GiveUp(restarts) => {
+                stdout_sender.send(format!(
+                    "monado-service was restarted {} times within {:.0}s, giving up.\n",
+                    restarts,
+                    window.as_secs_f32()
+                ));
+            }
+            RestartPlan::After(delay) => {
+                stdout_sender.send(format!(
+                    "Restarting monado-service in {:.1}s.\n",
+                    delay.as_secs_f32()
+                ));
+                self.pending_restart = Some((now + delay, status));
+            }
+        }
+    }
+
+    /// Starts monado-service again once a scheduled restart is due.
+    fn poll_restart(&mut self, logging_env_vars: &LoggingEnvVars, stdout_sender: &OutputSender) {
+        let Some((at, reason)) = self.pending_restart else {
+            return;
+        };
+        if Instant::now() < at {
+            return;
+        }
+        self.pending_restart = None;
+        self.restarts.push(Instant::now());
+        stdout_sender.clone().send(format!(
+            "──────── restart {} of {} after: {} ────────\n",
+            self.restarts.len(),
+            self.service_settings.max_restarts,
+            reason
+        ));
+        if let Err(err) = self.start_monado(logging_env_vars, stdout_sender.clone()) {
+            self.start_error = Some(err);
+        }
+    }
 }
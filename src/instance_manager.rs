@@ -0,0 +1,211 @@
+use crate::{instance::MonadoInstance, RexApp};
+use egui::{Context, Ui};
+use native_dialog::MessageDialog;
+use std::{error::Error, path::Path};
+
+pub fn update(state: &mut RexApp, ctx: &Context) {
+    egui::Window::new("Instances")
+        .default_open(false)
+        .collapsible(true)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut state.instance_name_input);
+            });
+            ui.horizontal(|ui| {
+                instance_buttons(state, ui);
+            });
+        });
+}
+
+fn instance_buttons(state: &mut RexApp, ui: &mut Ui) {
+    let name = state.instance_name_input.trim().to_string();
+    let selected = state.current_instance.clone();
+    let deleting = selected
+        .as_ref()
+        .is_some_and(|name| state.deleting_instances.contains(name));
+    let selected_idle = state
+        .current_instance()
+        .is_some_and(|i| i.child.is_none() && i.build_process.is_none());
+
+    let result = if ui.button("New").clicked() {
+        state.create_instance(&name)
+    } else if ui
+        .add_enabled(selected.is_some(), egui::Button::new("Clone"))
+        .on_hover_text("Copy the selected instance's settings into a new instance.")
+        .clicked()
+    {
+        state.clone_instance(selected.as_deref().unwrap_or_default(), &name)
+    } else if ui
+        .add_enabled(selected_idle, egui::Button::new("Rename"))
+        .clicked()
+    {
+        state.rename_instance(selected.as_deref().unwrap_or_default(), &name)
+    } else if ui
+        .add_enabled(selected.is_some() && !deleting, egui::Button::new("Delete"))
+        .clicked()
+    {
+        state.delete_instance(selected.as_deref().unwrap_or_default())
+    } else if ui.button("Refresh").clicked() {
+        state.load_instances()
+    } else {
+        return;
+    };
+
+    match result {
+        Ok(()) => state.instance_name_input.clear(),
+        Err(err) => show_error(err),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn show_error(err: Box<dyn Error>) {
+    println!("Instance error: {}", err);
+
+    MessageDialog::new()
+        .set_title("Instance Error")
+        .set_text(&format!("Unable to modify instance.\nError:\n{}", err))
+        .set_type(native_dialog::MessageType::Error)
+        .show_alert()
+        .expect("Error creating dialog window for instance error");
+}
+
+impl RexApp {
+    fn validate_new_instance_name(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(format!("'{}' is not a valid instance name.", name).into());
+        }
+        if self.monado_instance_dir.join(name).exists() {
+            return Err(format!("An instance named '{}' already exists.", name).into());
+        }
+        Ok(())
+    }
+
+    pub fn create_instance(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        self.validate_new_instance_name(name)?;
+        std::fs::create_dir_all(self.monado_instance_dir.join(name))?;
+        let instance = MonadoInstance::create_load(&self.monado_instance_dir, name.to_string())?;
+        instance.save()?;
+        self.insert_instance(name.to_string(), instance);
+        self.current_instance = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn clone_instance(&mut self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        self.validate_new_instance_name(to)?;
+        let from_instance = self
+            .instances
+            .get(from)
+            .ok_or_else(|| format!("No instance named '{}'.", from))?;
+        from_instance.save()?;
+        std::fs::create_dir_all(self.monado_instance_dir.join(to))?;
+        std::fs::copy(
+            self.monado_instance_dir.join(from).join("instance.toml"),
+            self.monado_instance_dir.join(to).join("instance.toml"),
+        )?;
+        // The per-instance config_v0.json belongs to the settings.
+        let from_config = from_instance.config_dir();
+        if from_config.is_dir() {
+            copy_dir(&from_config, &self.monado_instance_dir.join(to).join("config"))?;
+        }
+        let instance = MonadoInstance::create_load(&self.monado_instance_dir, to.to_string())?;
+        self.insert_instance(to.to_string(), instance);
+        self.current_instance = Some(to.to_string());
+        Ok(())
+    }
+
+    pub fn rename_instance(&mut self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        self.validate_new_instance_name(to)?;
+        let from_instance = self
+            .instances
+            .get(from)
+            .ok_or_else(|| format!("No instance named '{}'.", from))?;
+        if from_instance.has_children() {
+            return Err(format!("Instance '{}' is still running.", from).into());
+        }
+        std::fs::rename(
+            self.monado_instance_dir.join(from),
+            self.monado_instance_dir.join(to),
+        )?;
+        let old_instance = self.instances.remove(from);
+        let instance = MonadoInstance::create_load(&self.monado_instance_dir, to.to_string())?;
+        self.insert_instance(to.to_string(), instance);
+        // Keep the output, the instance is the same one under a new name.
+        if let (Some(old_instance), Some(instance)) = (old_instance, self.instances.get_mut(to)) {
+            instance.console = old_instance.console;
+        }
+        if self.current_instance.as_deref() == Some(from) {
+            self.current_instance = Some(to.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn delete_instance(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let confirmed = MessageDialog::new()
+            .set_title("Delete Instance")
+            .set_text(&format!(
+                "Delete instance '{}'?\nThis removes its settings, source checkout and build from disk.",
+                name
+            ))
+            .set_type(native_dialog::MessageType::Warning)
+            .show_confirm()?;
+        if !confirmed {
+            return Ok(());
+        }
+
+        if let Some(instance) = self.instances.get_mut(name) {
+            instance.kill_clients();
+            if let Some((_, mut build)) = instance.build_process.take() {
+                let _ = build.kill();
+                let _ = build.wait();
+            }
+            instance.restart_after_stop = false;
+            // Also cancels a pending restart, `poll_deletes` finishes once the service is gone.
+            let _ = instance.stop_monado();
+        }
+        self.deleting_instances.insert(name.to_string());
+        self.poll_deletes();
+        Ok(())
+    }
+
+    /// Removes the instances waiting to be deleted once their monado-service has stopped.
+    pub fn poll_deletes(&mut self) {
+        let stopped: Vec<String> = self
+            .deleting_instances
+            .iter()
+            .filter(|name| match self.instances.get(name.as_str()) {
+                Some(instance) => instance.child.is_none(),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        for name in stopped {
+            self.deleting_instances.remove(&name);
+            let instance = self.instances.remove(&name);
+            if let Err(err) = std::fs::remove_dir_all(self.monado_instance_dir.join(&name)) {
+                // Still there, keep showing it.
+                if let Some(instance) = instance {
+                    self.instances.insert(name, instance);
+                }
+                show_error(err.into());
+                continue;
+            }
+            if self.current_instance.as_deref() == Some(name.as_str()) {
+                self.current_instance = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ansi::{self, AnsiStyle};
+use crate::log_record::LogRecord;
+use egui::text::LayoutJob;
+use egui::{FontFamily, FontId};
+use std::collections::VecDeque;
+
+pub const CONSOLE_FONT_SIZE: f32 = 14.0;
+
+pub fn console_font() -> FontId {
+    FontId::new(CONSOLE_FONT_SIZE, FontFamily::Monospace)
+}
+
+/// A single line of output, parsed once when it arrives.
+pub struct LogLine {
+    /// The line as monado printed it, including ANSI escapes but without the newline.
+    pub raw: String,
+    pub job: LayoutJob,
+    /// `None` for lines that aren't Monado log records, they are shown verbatim.
+    pub record: Option<LogRecord>,
+}
+
+/// Keeps the newest `capacity` lines of a process' output.
+///
+/// Lines are addressed by their index since the buffer was created, so indices
+/// stay valid while older lines are dropped.
+pub struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    start: usize,
+    partial: String,
+    capacity: usize,
+    /// Style left by the previous line, terminals keep it until it is reset.
+    style: AnsiStyle,
+}
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        LogBuffer {
+            lines: VecDeque::new(),
+            start: 0,
+            partial: String::new(),
+            capacity: capacity.max(1),
+            style: AnsiStyle::default(),
+        }
+    }
+
+    /// Appends output, text after the last newline is held back until its line is complete.
+    pub fn push_str(&mut self, text: &str) {
+        self.partial.push_str(text);
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.push_line(line.trim_end_matches(['\n', '\r']));
+        }
+    }
+
+    fn push_line(&mut self, raw: &str) {
+        let job = ansi::layout_line(raw, &mut self.style, &console_font());
+        let record = LogRecord::parse(&job.text);
+        self.lines.push_back(LogLine {
+            raw: raw.to_string(),
+            job,
+            record,
+        });
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        while self.lines.len() > self.capacity {
+            self.lines.pop_front();
+            self.start += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = self.end();
+        self.lines.clear();
+        self.partial.clear();
+        self.style = AnsiStyle::default();
+    }
+
+    /// Index of the oldest line still in the buffer.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Index the next line will get.
+    pub fn end(&self) -> usize {
+        self.start + self.lines.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&LogLine> {
+        self.lines.get(index.checked_sub(self.start)?)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.truncate();
+    }
+
+    /// The buffered output with all ANSI escapes removed.
+    pub fn plain_text(&self) -> String {
+        let mut output = String::new();
+        for line in &self.lines {
+            output.push_str(&line.job.text);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::child_output::OutputSender;
+    use egui::Context;
+    use std::sync::mpsc::sync_channel;
+
+    fn texts(buffer: &LogBuffer) -> Vec<String> {
+        (buffer.start()..buffer.end())
+            .map(|i| buffer.get(i).unwrap().job.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_lines() {
+        let mut buffer = LogBuffer::new(3);
+        buffer.push_str("1\n2\n3\n4\n5\n");
+        assert_eq!((buffer.start(), buffer.end()), (2, 5));
+        assert_eq!(texts(&buffer), ["3", "4", "5"]);
+        assert!(buffer.get(1).is_none());
+        assert_eq!(buffer.get(4).unwrap().raw, "5");
+
+        buffer.set_capacity(1);
+        assert_eq!(texts(&buffer), ["5"]);
+        assert_eq!(buffer.start(), 4);
+
+        buffer.clear();
+        assert_eq!((buffer.start(), buffer.end()), (5, 5));
+        buffer.push_str("6\n");
+        assert_eq!(buffer.get(5).unwrap().raw, "6");
+    }
+
+    #[test]
+    fn joins_partial_lines() {
+        let mut buffer = LogBuffer::new(10);
+        buffer.push_str("INFO [comp_");
+        assert_eq!(buffer.end(), 0);
+        buffer.push_str("main] half");
+        buffer.push_str(" done\r\n\x1b[31mred");
+        assert_eq!(texts(&buffer), ["INFO [comp_main] half done"]);
+        let record = buffer.get(0).unwrap().record.as_ref().unwrap();
+        assert_eq!(record.function.as_deref(), Some("comp_main"));
+        assert_eq!(record.message, "half done");
+
+        buffer.push_str(" line\n");
+        assert_eq!(buffer.get(1).unwrap().raw, "\x1b[31mred line");
+        assert_eq!(
+            buffer.plain_text(),
+            "INFO [comp_main] half done\nred line\n"
+        );
+    }
+
+    #[test]
+    fn shows_the_dropped_line_summary_verbatim() {
+        let (sender, receiver) = sync_channel(1);
+        let mut sender = OutputSender::new(sender, Some(Context::default()));
+        let mut buffer = LogBuffer::new(10);
+        for line in ["INFO [a] kept\n", "INFO [a] dropped\n", "INFO [a] too\n"] {
+            sender.send(line.to_string());
+        }
+        buffer.push_str(&receiver.recv().unwrap());
+        // The summary takes the room that frees up.
+        sender.send("INFO [a] after\n".to_string());
+        buffer.push_str(&receiver.recv().unwrap());
+
+        assert_eq!(
+            texts(&buffer),
+            [
+                "INFO [a] kept",
+                "[rex] 2 lines dropped, the console couldn't keep up."
+            ]
+        );
+        assert!(buffer.get(1).unwrap().record.is_none());
+    }
+}
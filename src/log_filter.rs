@@ -0,0 +1,400 @@
+use crate::log_buffer::{LogBuffer, LogLine};
+use crate::log_options::LoggingLevel;
+use egui::text::{LayoutJob, LayoutSection};
+use egui::{Color32, ComboBox, RichText, Ui};
+use regex::{Regex, RegexBuilder};
+
+/// Which console lines are shown and what is searched for.
+///
+/// The visible lines are cached and only new output is scanned each frame,
+/// everything is rescanned when the filter itself changes.
+#[derive(Default)]
+pub struct LogFilter {
+    pub min_level: Option<LoggingLevel>,
+    pub include_functions: String,
+    pub exclude_functions: String,
+    pub search: String,
+    pub search_is_regex: bool,
+
+    search_regex: Option<Regex>,
+    search_error: Option<String>,
+    /// Buffer indices of the lines that pass the filter.
+    visible: Vec<usize>,
+    /// Buffer indices of visible lines containing a search match.
+    hits: Vec<usize>,
+    current_hit: Option<usize>,
+    scanned: usize,
+    last_record_hidden: bool,
+    /// Row to scroll to on the next frame.
+    pub scroll_to_row: Option<usize>,
+}
+impl LogFilter {
+    pub fn update(&mut self, ui: &mut Ui) {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("console_min_level")
+                .selected_text(match self.min_level {
+                    Some(level) => level.rich_text(),
+                    None => RichText::new("All Levels"),
+                })
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut self.min_level, None, "All Levels")
+                        .changed();
+                    for level in LoggingLevel::ALL {
+                        changed |= ui
+                            .selectable_value(&mut self.min_level, Some(level), level.rich_text())
+                            .changed();
+                    }
+                });
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.include_functions)
+                        .hint_text("Only functions")
+                        .desired_width(120.0),
+                )
+                .on_hover_text(
+                    "Comma separated, shows records whose function contains any of these.",
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.exclude_functions)
+                        .hint_text("Hide functions")
+                        .desired_width(120.0),
+                )
+                .on_hover_text(
+                    "Comma separated, hides records whose function contains any of these.",
+                )
+                .changed();
+
+            ui.separator();
+
+            let search_changed = ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.search)
+                        .hint_text("Search")
+                        .desired_width(160.0),
+                )
+                .changed()
+                | ui.checkbox(&mut self.search_is_regex, "Regex").changed();
+            if search_changed {
+                self.compile_search();
+                changed = true;
+            }
+            if ui
+                .small_button("⏶")
+                .on_hover_text("Previous match")
+                .clicked()
+            {
+                self.step_hit(false);
+            }
+            if ui.small_button("⏷").on_hover_text("Next match").clicked() {
+                self.step_hit(true);
+            }
+            match (&self.search_error, self.current_hit) {
+                (Some(err), _) => {
+                    ui.colored_label(Color32::LIGHT_RED, "Invalid regex")
+                        .on_hover_text(err);
+                }
+                (None, Some(hit)) => {
+                    ui.label(format!("{}/{}", hit + 1, self.hits.len()));
+                }
+                (None, None) if self.search_regex.is_some() => {
+                    ui.label(format!("{} matches", self.hits.len()));
+                }
+                _ => {}
+            }
+        });
+        if changed {
+            self.reset();
+        }
+    }
+
+    fn compile_search(&mut self) {
+        self.search_error = None;
+        self.search_regex = None;
+        if self.search.is_empty() {
+            return;
+        }
+        let pattern = if self.search_is_regex {
+            self.search.clone()
+        } else {
+            regex::escape(&self.search)
+        };
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(!self.search_is_regex)
+            .build()
+        {
+            Ok(regex) => self.search_regex = Some(regex),
+            Err(err) => self.search_error = Some(err.to_string()),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.visible.clear();
+        self.hits.clear();
+        self.current_hit = None;
+        self.scanned = 0;
+        self.last_record_hidden = false;
+    }
+
+    /// Drops lines that left the buffer and scans the ones that arrived since the last frame.
+    pub fn sync(&mut self, buffer: &LogBuffer) {
+        let start = buffer.start();
+        let dropped = self.visible.partition_point(|&i| i < start);
+        self.visible.drain(..dropped);
+        let dropped_hits = self.hits.partition_point(|&i| i < start);
+        self.hits.drain(..dropped_hits);
+        self.current_hit = self
+            .current_hit
+            .and_then(|hit| hit.checked_sub(dropped_hits));
+
+        for index in self.scanned.max(start)..buffer.end() {
+            let Some(line) = buffer.get(index) else {
+                continue;
+            };
+            if !self.is_visible(line) {
+                continue;
+            }
+            self.visible.push(index);
+            if let Some(regex) = &self.search_regex {
+                if regex.is_match(&line.job.text) {
+                    self.hits.push(index);
+                }
+            }
+        }
+        self.scanned = buffer.end();
+    }
+
+    /// Lines that aren't records follow the record before them, they are usually part of its message.
+    fn is_visible(&mut self, line: &LogLine) -> bool {
+        let Some(record) = &line.record else {
+            return !self.last_record_hidden;
+        };
+        let function = record.function.as_deref().unwrap_or_default();
+        let matches_any = |list: &str| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .any(|f| function.contains(f))
+        };
+        let include = self.include_functions.trim();
+        let visible = self.min_level.is_none_or(|min| record.level >= min)
+            && (include.is_empty() || matches_any(include))
+            && !matches_any(&self.exclude_functions);
+        self.last_record_hidden = !visible;
+        visible
+    }
+
+    fn step_hit(&mut self, forward: bool) {
+        if self.hits.is_empty() {
+            self.current_hit = None;
+            return;
+        }
+        let last = self.hits.len() - 1;
+        let hit = match (self.current_hit, forward) {
+            (None, true) => 0,
+            (None, false) => last,
+            (Some(hit), true) => {
+                if hit >= last {
+                    0
+                } else {
+                    hit + 1
+                }
+            }
+            (Some(hit), false) => {
+                if hit == 0 {
+                    last
+                } else {
+                    hit - 1
+                }
+            }
+        };
+        self.current_hit = Some(hit);
+        self.scroll_to_row = self.visible.binary_search(&self.hits[hit]).ok();
+    }
+
+    pub fn visible_len(&self) -> usize {
+        self.visible.len()
+    }
+
+    /// Buffer index of a visible row.
+    pub fn visible_line(&self, row: usize) -> Option<usize> {
+        self.visible.get(row).copied()
+    }
+
+    pub fn is_current_hit(&self, index: usize) -> bool {
+        self.current_hit.map(|hit| self.hits[hit]) == Some(index)
+    }
+
+    /// The line's layout with every search match given a background.
+    pub fn highlight(&self, line: &LogLine, current: bool) -> LayoutJob {
+        let Some(regex) = &self.search_regex else {
+            return line.job.clone();
+        };
+        let matches: Vec<_> = regex
+            .find_iter(&line.job.text)
+            .map(|m| m.range())
+            .filter(|r| !r.is_empty())
+            .collect();
+        if matches.is_empty() {
+            return line.job.clone();
+        }
+        let background = if current {
+            Color32::from_rgb(160, 110, 0)
+        } else {
+            Color32::from_rgb(80, 60, 0)
+        };
+
+        let mut job = LayoutJob {
+            sections: Vec::new(),
+            ..line.job.clone()
+        };
+        for section in &line.job.sections {
+            let range = section.byte_range.clone();
+            let mut pos = range.start;
+            let mut push = |byte_range: std::ops::Range<usize>, highlighted: bool| {
+                if byte_range.is_empty() {
+                    return;
+                }
+                let mut format = section.format.clone();
+                if highlighted {
+                    format.background = background;
+                    format.color = Color32::WHITE;
+                }
+                job.sections.push(LayoutSection {
+                    leading_space: if byte_range.start == range.start {
+                        section.leading_space
+                    } else {
+                        0.0
+                    },
+                    byte_range,
+                    format,
+                });
+            };
+            for m in matches
+                .iter()
+                .filter(|m| m.start < range.end && m.end > range.start)
+            {
+                push(pos..m.start.max(pos), false);
+                pos = m.start.max(pos);
+                push(pos..m.end.min(range.end), true);
+                pos = m.end.min(range.end);
+            }
+            push(pos..range.end, false);
+        }
+        job
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> LogBuffer {
+        let mut buffer = LogBuffer::new(100);
+        buffer.push_str(text);
+        buffer
+    }
+
+    fn visible(filter: &mut LogFilter, buffer: &LogBuffer) -> Vec<String> {
+        filter.reset();
+        filter.sync(buffer);
+        (0..filter.visible_len())
+            .filter_map(|row| buffer.get(filter.visible_line(row)?))
+            .map(|line| line.job.text.clone())
+            .collect()
+    }
+
+    const OUTPUT: &str = "DEBUG [comp_main] init\n\
+        \tdetail of init\n\
+        INFO [oxr_session] session created\n\
+        WARN [comp_main] slow frame\n\
+        \tdetail of slow frame\n\
+        ERROR [u_config_json] broken config\n";
+
+    #[test]
+    fn filters_by_level() {
+        let buffer = buffer(OUTPUT);
+        let mut filter = LogFilter::default();
+        assert_eq!(visible(&mut filter, &buffer).len(), 6);
+
+        filter.min_level = Some(LoggingLevel::Warn);
+        assert_eq!(
+            visible(&mut filter, &buffer),
+            [
+                "WARN [comp_main] slow frame",
+                "\tdetail of slow frame",
+                "ERROR [u_config_json] broken config"
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_by_function() {
+        let buffer = buffer(OUTPUT);
+        let mut filter = LogFilter {
+            include_functions: "comp_, u_config".to_string(),
+            ..LogFilter::default()
+        };
+        assert_eq!(
+            visible(&mut filter, &buffer),
+            [
+                "DEBUG [comp_main] init",
+                "\tdetail of init",
+                "WARN [comp_main] slow frame",
+                "\tdetail of slow frame",
+                "ERROR [u_config_json] broken config"
+            ]
+        );
+
+        filter.exclude_functions = " comp_main ,".to_string();
+        assert_eq!(
+            visible(&mut filter, &buffer),
+            ["ERROR [u_config_json] broken config"]
+        );
+    }
+
+    #[test]
+    fn steps_through_hits() {
+        let buffer = buffer(OUTPUT);
+        let mut filter = LogFilter {
+            min_level: Some(LoggingLevel::Info),
+            search: "FRAME".to_string(),
+            ..LogFilter::default()
+        };
+        filter.compile_search();
+        filter.sync(&buffer);
+        // "slow frame" and its detail, the first row is the INFO line.
+        assert_eq!(filter.hits, [3, 4]);
+        assert_eq!(filter.current_hit, None);
+
+        filter.step_hit(false);
+        assert_eq!(filter.current_hit, Some(1));
+        assert_eq!(filter.scroll_to_row, Some(2));
+        filter.step_hit(true);
+        assert_eq!(filter.current_hit, Some(0));
+        assert!(filter.is_current_hit(3));
+        assert_eq!(filter.scroll_to_row, Some(1));
+        filter.step_hit(true);
+        filter.step_hit(true);
+        assert_eq!(filter.current_hit, Some(0));
+
+        // Searching as a regex is case sensitive.
+        filter.search = "Frame|config".to_string();
+        filter.search_is_regex = true;
+        filter.compile_search();
+        filter.reset();
+        filter.sync(&buffer);
+        assert_eq!(filter.hits, [5]);
+
+        filter.search = "(".to_string();
+        filter.compile_search();
+        assert!(filter.search_error.is_some());
+        filter.reset();
+        filter.sync(&buffer);
+        filter.step_hit(true);
+        assert_eq!(filter.current_hit, None);
+    }
+}
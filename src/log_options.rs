@@ -1,63 +1,239 @@
+use crate::env_var::EnvSink;
 use crate::RexApp;
 use egui::{Color32, ComboBox, Context, RichText, Ui};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
-use subprocess::Exec;
+use std::fmt;
 
 pub fn update(state: &mut RexApp, ctx: &Context) {
     egui::Window::new("Logging Options")
         .default_pos([512.0, 0.0])
         .collapsible(true)
         .show(ctx, |ui| {
-            log_level_dropdown(state, ui, "Compositor", |s| {
-                &mut s.logging_env_vars.compositor_log
-            });
+            for group in LOG_GROUPS {
+                ui.collapsing(group.name, |ui| {
+                    set_group_dropdown(state, ui, group);
+                    ui.separator();
+                    for var in group.vars {
+                        log_level_dropdown(state, ui, var.name, var.env, |s| {
+                            (var.field)(&mut s.logging_env_vars)
+                        });
+                    }
+                });
+            }
         });
 }
 
-fn log_level_dropdown<F: FnOnce(&mut RexApp) -> &mut LoggingLevel>(
+fn set_group_dropdown(state: &mut RexApp, ui: &mut Ui, group: &LogGroup) {
+    let mut group_level = None;
+    ComboBox::from_id_source(group.name)
+        .selected_text("Set all in group")
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(false, "Unset").clicked() {
+                group_level = Some(None);
+            }
+            for level in LoggingLevel::ALL {
+                if ui.selectable_label(false, level.rich_text()).clicked() {
+                    group_level = Some(Some(level));
+                }
+            }
+        });
+    if let Some(level) = group_level {
+        for var in group.vars {
+            *(var.field)(&mut state.logging_env_vars) = level;
+        }
+        state.save_global();
+    }
+}
+
+fn log_level_dropdown<F: FnOnce(&mut RexApp) -> &mut Option<LoggingLevel>>(
     state: &mut RexApp,
     ui: &mut Ui,
     name: &str,
+    env: &str,
     log_level_fn: F,
 ) {
     let log_level = log_level_fn(state);
-    let old_value = log_level.clone();
+    let old_value = *log_level;
     ComboBox::from_label(name)
-        .selected_text(format!("{:?}", log_level))
+        .selected_text(match log_level {
+            Some(level) => level.rich_text(),
+            None => RichText::new("Unset").color(Color32::GRAY),
+        })
         .show_ui(ui, |ui| {
-            ui.selectable_value(
-                log_level,
-                LoggingLevel::Trace,
-                RichText::new("Trace").color(Color32::LIGHT_GRAY),
-            );
-            ui.selectable_value(
-                log_level,
-                LoggingLevel::Debug,
-                RichText::new("Debug").color(Color32::DEBUG_COLOR),
-            );
-            ui.selectable_value(
-                log_level,
-                LoggingLevel::Info,
-                RichText::new("Info").color(Color32::LIGHT_BLUE),
-            );
-            ui.selectable_value(
-                log_level,
-                LoggingLevel::Warn,
-                RichText::new("Warn").color(Color32::YELLOW),
-            );
-            ui.selectable_value(
-                log_level,
-                LoggingLevel::Error,
-                RichText::new("Error").color(Color32::LIGHT_RED),
-            );
-        });
+            ui.selectable_value(log_level, None, "Unset")
+                .on_hover_text("Don't set the variable and let Monado use its default.");
+            for level in LoggingLevel::ALL {
+                ui.selectable_value(log_level, Some(level), level.rich_text());
+            }
+        })
+        .response
+        .on_hover_text(env);
     if *log_level != old_value {
         state.save_global();
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Copy, Clone)]
+struct LogVar {
+    name: &'static str,
+    env: &'static str,
+    field: fn(&mut LoggingEnvVars) -> &mut Option<LoggingLevel>,
+}
+
+struct LogGroup {
+    name: &'static str,
+    vars: &'static [LogVar],
+}
+
+const LOG_GROUPS: &[LogGroup] = &[
+    LogGroup {
+        name: "Core",
+        vars: &[
+            LogVar {
+                name: "Global",
+                env: "XRT_LOG",
+                field: |v| &mut v.global_log,
+            },
+            LogVar {
+                name: "JSON",
+                env: "JSON_LOG",
+                field: |v| &mut v.json_log,
+            },
+        ],
+    },
+    LogGroup {
+        name: "Compositor",
+        vars: &[
+            LogVar {
+                name: "Compositor",
+                env: "XRT_COMPOSITOR_LOG",
+                field: |v| &mut v.compositor_log,
+            },
+            LogVar {
+                name: "App Pacing",
+                env: "U_PACING_APP_LOG",
+                field: |v| &mut v.u_pacing_app_log,
+            },
+            LogVar {
+                name: "Compositor Pacing",
+                env: "U_PACING_COMPOSITOR_LOG",
+                field: |v| &mut v.u_pacing_compositor_log,
+            },
+            LogVar {
+                name: "EGL",
+                env: "EGL_LOG",
+                field: |v| &mut v.egl_log,
+            },
+            LogVar {
+                name: "EGL Swapchain",
+                env: "EGL_SWAPCHAIN_LOG",
+                field: |v| &mut v.egl_swap_chain_log,
+            },
+            LogVar {
+                name: "D3D Compositor",
+                env: "D3D_COMPOSITOR_LOG",
+                field: |v| &mut v.d3d_compositor_log,
+            },
+            LogVar {
+                name: "D3D11/DXGI",
+                env: "DXGI_LOG",
+                field: |v| &mut v.d3d11_log,
+            },
+            LogVar {
+                name: "AHardwareBuffer",
+                env: "AHARDWAREBUFFER_LOG",
+                field: |v| &mut v.ahardwarebuffer_log,
+            },
+        ],
+    },
+    LogGroup {
+        name: "Tracking",
+        vars: &[
+            LogVar {
+                name: "Hand Tracking",
+                env: "HT_LOG",
+                field: |v| &mut v.ht_log,
+            },
+            LogVar {
+                name: "Mercury",
+                env: "MERCURY_LOG",
+                field: |v| &mut v.mercury_log,
+            },
+            LogVar {
+                name: "SLAM",
+                env: "SLAM_LOG",
+                field: |v| &mut v.slam_log,
+            },
+            LogVar {
+                name: "Simple IMU",
+                env: "SIMPLE_IMU_LOG",
+                field: |v| &mut v.simple_imu_log,
+            },
+            LogVar {
+                name: "PSVR Tracking",
+                env: "PSVR_TRACKING_LOG",
+                field: |v| &mut v.psvr_tracking_log,
+            },
+            LogVar {
+                name: "Calibration",
+                env: "CALIB_LOG",
+                field: |v| &mut v.calibration_log,
+            },
+            LogVar {
+                name: "Auto Exposure/Gain",
+                env: "AEG_LOG",
+                field: |v| &mut v.aeg_log,
+            },
+        ],
+    },
+    LogGroup {
+        name: "Drivers",
+        vars: &[
+            LogVar {
+                name: "Lighthouse",
+                env: "LH_LOG",
+                field: |v| &mut v.lh_log,
+            },
+            LogVar {
+                name: "libsurvive",
+                env: "SURVIVE_LOG",
+                field: |v| &mut v.survive_log,
+            },
+            LogVar {
+                name: "Vive",
+                env: "VIVE_LOG",
+                field: |v| &mut v.vive_log,
+            },
+            LogVar {
+                name: "SimulaVR",
+                env: "SVR_LOG",
+                field: |v| &mut v.svr_log,
+            },
+            LogVar {
+                name: "North Star",
+                env: "NS_LOG",
+                field: |v| &mut v.ns_log,
+            },
+            LogVar {
+                name: "Qwerty",
+                env: "QWERTY_LOG",
+                field: |v| &mut v.qwerty_log,
+            },
+            LogVar {
+                name: "Arduino",
+                env: "ARDUINO_LOG",
+                field: |v| &mut v.arduino_log,
+            },
+            LogVar {
+                name: "Razer Hydra",
+                env: "HYDRA_LOG",
+                field: |v| &mut v.hydra_log,
+            },
+        ],
+    },
+];
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 pub enum LoggingLevel {
     Trace,
     Debug,
@@ -65,103 +241,106 @@ pub enum LoggingLevel {
     Warn,
     Error,
 }
-impl ToString for LoggingLevel {
-    fn to_string(&self) -> String {
+impl LoggingLevel {
+    pub const ALL: [LoggingLevel; 5] = [
+        LoggingLevel::Trace,
+        LoggingLevel::Debug,
+        LoggingLevel::Info,
+        LoggingLevel::Warn,
+        LoggingLevel::Error,
+    ];
+
+    pub fn rich_text(&self) -> RichText {
+        match self {
+            LoggingLevel::Trace => RichText::new("Trace").color(Color32::LIGHT_GRAY),
+            LoggingLevel::Debug => RichText::new("Debug").color(Color32::DEBUG_COLOR),
+            LoggingLevel::Info => RichText::new("Info").color(Color32::LIGHT_BLUE),
+            LoggingLevel::Warn => RichText::new("Warn").color(Color32::YELLOW),
+            LoggingLevel::Error => RichText::new("Error").color(Color32::LIGHT_RED),
+        }
+    }
+}
+impl fmt::Display for LoggingLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LoggingLevel::Trace => String::from("trace"),
-            LoggingLevel::Debug => String::from("debug"),
-            LoggingLevel::Info => String::from("info"),
-            LoggingLevel::Warn => String::from("warn"),
-            LoggingLevel::Error => String::from("error"),
+            LoggingLevel::Trace => write!(f, "trace"),
+            LoggingLevel::Debug => write!(f, "debug"),
+            LoggingLevel::Info => write!(f, "info"),
+            LoggingLevel::Warn => write!(f, "warn"),
+            LoggingLevel::Error => write!(f, "error"),
         }
     }
 }
+
+/// Log level for each Monado subsystem, `None` leaves the variable unset so Monado's default applies.
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 pub struct LoggingEnvVars {
-    pub compositor_log: LoggingLevel,
-    pub egl_swap_chain_log: LoggingLevel,
-    pub d3d_compositor_log: LoggingLevel,
-    pub ht_log: LoggingLevel,
-    pub calibration_log: LoggingLevel,
-    pub global_log: LoggingLevel,
-    pub aeg_log: LoggingLevel,
-    pub egl_log: LoggingLevel,
-    pub mercury_log: LoggingLevel,
-    pub slam_log: LoggingLevel,
-    pub simple_imu_log: LoggingLevel,
-    pub psvr_tracking_log: LoggingLevel,
-    pub d3d11_log: LoggingLevel,
-    pub u_pacing_app_log: LoggingLevel,
-    pub u_pacing_compositor_log: LoggingLevel,
-    pub json_log: LoggingLevel,
-    pub ahardwarebuffer_log: LoggingLevel,
-    pub lh_log: LoggingLevel,
-    pub svr_log: LoggingLevel,
-    pub ns_log: LoggingLevel,
-    pub qwerty_log: LoggingLevel,
-    pub arduino_log: LoggingLevel,
-    pub hydra_log: LoggingLevel,
-    pub survive_log: LoggingLevel,
-    pub vive_log: LoggingLevel,
+    pub compositor_log: Option<LoggingLevel>,
+    pub egl_swap_chain_log: Option<LoggingLevel>,
+    pub d3d_compositor_log: Option<LoggingLevel>,
+    pub ht_log: Option<LoggingLevel>,
+    pub calibration_log: Option<LoggingLevel>,
+    pub global_log: Option<LoggingLevel>,
+    pub aeg_log: Option<LoggingLevel>,
+    pub egl_log: Option<LoggingLevel>,
+    pub mercury_log: Option<LoggingLevel>,
+    pub slam_log: Option<LoggingLevel>,
+    pub simple_imu_log: Option<LoggingLevel>,
+    pub psvr_tracking_log: Option<LoggingLevel>,
+    pub d3d11_log: Option<LoggingLevel>,
+    pub u_pacing_app_log: Option<LoggingLevel>,
+    pub u_pacing_compositor_log: Option<LoggingLevel>,
+    pub json_log: Option<LoggingLevel>,
+    pub ahardwarebuffer_log: Option<LoggingLevel>,
+    pub lh_log: Option<LoggingLevel>,
+    pub svr_log: Option<LoggingLevel>,
+    pub ns_log: Option<LoggingLevel>,
+    pub qwerty_log: Option<LoggingLevel>,
+    pub arduino_log: Option<LoggingLevel>,
+    pub hydra_log: Option<LoggingLevel>,
+    pub survive_log: Option<LoggingLevel>,
+    pub vive_log: Option<LoggingLevel>,
 }
 impl Default for LoggingEnvVars {
     fn default() -> Self {
         LoggingEnvVars {
-            compositor_log: LoggingLevel::Debug,
-            egl_swap_chain_log: LoggingLevel::Warn,
-            d3d_compositor_log: LoggingLevel::Debug,
-            ht_log: LoggingLevel::Warn,
-            calibration_log: LoggingLevel::Debug,
-            global_log: LoggingLevel::Warn,
-            aeg_log: LoggingLevel::Warn,
-            egl_log: LoggingLevel::Info,
-            mercury_log: LoggingLevel::Warn,
-            slam_log: LoggingLevel::Debug,
-            simple_imu_log: LoggingLevel::Warn,
-            psvr_tracking_log: LoggingLevel::Warn,
-            d3d11_log: LoggingLevel::Warn,
-            u_pacing_app_log: LoggingLevel::Warn,
-            u_pacing_compositor_log: LoggingLevel::Warn,
-            json_log: LoggingLevel::Warn,
-            ahardwarebuffer_log: LoggingLevel::Warn,
-            lh_log: LoggingLevel::Warn,
-            svr_log: LoggingLevel::Warn,
-            ns_log: LoggingLevel::Warn,
-            qwerty_log: LoggingLevel::Info,
-            arduino_log: LoggingLevel::Warn,
-            hydra_log: LoggingLevel::Warn,
-            survive_log: LoggingLevel::Warn,
-            vive_log: LoggingLevel::Warn,
+            compositor_log: Some(LoggingLevel::Debug),
+            egl_swap_chain_log: None,
+            d3d_compositor_log: None,
+            ht_log: None,
+            calibration_log: None,
+            global_log: None,
+            aeg_log: None,
+            egl_log: None,
+            mercury_log: None,
+            slam_log: None,
+            simple_imu_log: None,
+            psvr_tracking_log: None,
+            d3d11_log: None,
+            u_pacing_app_log: None,
+            u_pacing_compositor_log: None,
+            json_log: None,
+            ahardwarebuffer_log: None,
+            lh_log: None,
+            svr_log: None,
+            ns_log: None,
+            qwerty_log: None,
+            arduino_log: None,
+            hydra_log: None,
+            survive_log: None,
+            vive_log: None,
         }
     }
 }
 impl LoggingEnvVars {
-    pub fn set_vars(&self, mut command: Exec) -> Exec {
-        command = command.env("XRT_COMPOSITOR_LOG", self.compositor_log.to_string());
-        // command = command.env("EGL_SWAPCHAIN_LOG", self.egl_swap_chain_log.to_string());
-        // command = command.env("D3D_COMPOSITOR_LOG", self.d3d_compositor_log.to_string());
-        // command = command.env("HT_LOG", self.ht_log.to_string());
-        // command = command.env("CALIB_LOG", self.calibration_log.to_string());
-        // command = command.env("XRT_LOG", self.global_log.to_string());
-        // command = command.env("AEG_LOG", self.aeg_log.to_string());
-        // command = command.env("EGL_LOG", self.egl_log.to_string());
-        // command = command.env("MERCURY_LOG", self.mercury_log.to_string());
-        // command = command.env("SLAM_LOG", self.slam_log.to_string());
-        // command = command.env("SIMPLE_IMU_LOG", self.simple_imu_log.to_string());
-        // command = command.env("PSVR_TRACKING_LOG", self.psvr_tracking_log.to_string());
-        // command = command.env("DXGI_LOG", self.d3d11_log.to_string());
-        // command = command.env("U_PACING_APP_LOG", self.u_pacing_app_log.to_string());
-        // command = command.env("U_PACING_COMPOSITOR_LOG", self.u_pacing_compositor_log.to_string());
-        // command = command.env("JSON_LOG", self.json_log.to_string());
-        // command = command.env("AHARDWAREBUFFER_LOG", self.json_log.to_string());
-        // command = command.env("LH_LOG", self.lh_log.to_string());
-        // command = command.env("SVR_LOG", self.svr_log.to_string());
-        // command = command.env("NS_LOG", self.ns_log.to_string());
-        // command = command.env("QWERTY_LOG", self.qwerty_log.to_string());
-        // command = command.env("ARDUINO_LOG", self.arduino_log.to_string());
-        // command = command.env("HYDRA_LOG", self.hydra_log.to_string());
-        // command = command.env("SURVIVE_LOG", self.survive_log.to_string());
-        // command = command.env("VIVE_LOG", self.vive_log.to_string());
+    pub fn set_vars<C: EnvSink>(&self, mut command: C) -> C {
+        // The accessors need a mutable reference, this is a cheap copy.
+        let mut vars = *self;
+        for var in LOG_GROUPS.iter().flat_map(|g| g.vars) {
+            if let Some(level) = *(var.field)(&mut vars) {
+                command = command.env(var.env, level.to_string());
+            }
+        }
         command
     }
 }
@@ -0,0 +1,122 @@
+use crate::log_options::LoggingLevel;
+
+/// A line of Monado output split into its parts, e.g. `DEBUG [comp_main] Doing a thing`.
+///
+/// A leading timestamp is skipped, the console shows it as part of the line anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: LoggingLevel,
+    /// The function Monado logged from, the closest thing its output has to a module name.
+    pub function: Option<String>,
+    pub message: String,
+}
+impl LogRecord {
+    /// Parses a line with ANSI escapes already removed, `None` if it isn't a Monado log line.
+    pub fn parse(line: &str) -> Option<LogRecord> {
+        let mut rest = line.trim_start();
+
+        if let Some(after) = skip_timestamp(rest) {
+            rest = after.trim_start();
+        }
+
+        let (level_word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+        let level = parse_level(level_word.trim_end_matches(':'))?;
+        rest = after.trim_start();
+
+        let mut function = None;
+        if let Some(after_bracket) = rest.strip_prefix('[') {
+            if let Some((name, after)) = after_bracket.split_once(']') {
+                if !name.is_empty() && !name.contains(char::is_whitespace) {
+                    function = Some(name.to_string());
+                    rest = after.strip_prefix(' ').unwrap_or(after);
+                }
+            }
+        }
+
+        Some(LogRecord {
+            level,
+            function,
+            message: rest.to_string(),
+        })
+    }
+}
+
+fn parse_level(word: &str) -> Option<LoggingLevel> {
+    match word.to_ascii_uppercase().as_str() {
+        "TRACE" => Some(LoggingLevel::Trace),
+        "DEBUG" => Some(LoggingLevel::Debug),
+        "INFO" => Some(LoggingLevel::Info),
+        "WARN" | "WARNING" => Some(LoggingLevel::Warn),
+        "ERROR" => Some(LoggingLevel::Error),
+        _ => None,
+    }
+}
+
+fn is_timestamp(text: &str) -> bool {
+    text.contains(|c: char| c.is_ascii_digit())
+        && text.contains([':', '.'])
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ':' | '.' | '-' | ',' | 'T' | ' '))
+}
+
+/// What follows a leading `[12:34:56.789]` or `12:34:56.789` style timestamp.
+fn skip_timestamp(line: &str) -> Option<&str> {
+    if let Some(after_bracket) = line.strip_prefix('[') {
+        let (ts, rest) = after_bracket.split_once(']')?;
+        return is_timestamp(ts).then_some(rest);
+    }
+    let (ts, rest) = line.split_once(' ')?;
+    is_timestamp(ts).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_records() {
+        assert_eq!(
+            LogRecord::parse("DEBUG [comp_main] Doing a thing"),
+            Some(LogRecord {
+                level: LoggingLevel::Debug,
+                function: Some("comp_main".to_string()),
+                message: "Doing a thing".to_string(),
+            })
+        );
+        assert_eq!(
+            LogRecord::parse("[12:34:56.789] WARNING: [u_config_json] No config file"),
+            Some(LogRecord {
+                level: LoggingLevel::Warn,
+                function: Some("u_config_json".to_string()),
+                message: "No config file".to_string(),
+            })
+        );
+        assert_eq!(
+            LogRecord::parse("2024-01-02T03:04:05.678 error [oxr_xrCreateInstance]"),
+            Some(LogRecord {
+                level: LoggingLevel::Error,
+                function: Some("oxr_xrCreateInstance".to_string()),
+                message: String::new(),
+            })
+        );
+        // A bracket with spaces in it is part of the message, not a function.
+        assert_eq!(
+            LogRecord::parse(" INFO [not a function] message"),
+            Some(LogRecord {
+                level: LoggingLevel::Info,
+                function: None,
+                message: "[not a function] message".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_other_lines() {
+        assert_eq!(LogRecord::parse(""), None);
+        assert_eq!(LogRecord::parse("\tDevices:"), None);
+        assert_eq!(LogRecord::parse("[rex] 3 lines dropped"), None);
+        assert_eq!(LogRecord::parse("[12:34:56] Information only"), None);
+        assert_eq!(LogRecord::parse("Information: nothing"), None);
+    }
+}
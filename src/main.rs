@@ -1,29 +1,49 @@
+mod ansi;
+mod builder;
+mod child_output;
+mod clients;
+mod cli;
 mod compositor;
 mod console;
 mod control_panel;
+mod displays;
 mod env_var;
 pub mod instance;
+mod instance_manager;
+mod log_buffer;
+mod log_filter;
 mod log_options;
-
-
+mod log_record;
+mod monado_config;
+mod openxr_runtime;
+mod service;
+mod session_log;
+mod vk_display;
+
+use console::{Console, ConsoleSettings};
 use eframe::{App, Frame};
 use egui::{Color32, Context, Style, Visuals};
 use instance::MonadoInstance;
 use log_options::LoggingEnvVars;
 use native_dialog::MessageDialog;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use service::ServiceStatus;
 use std::{
     iter::FromIterator,
     path::PathBuf,
-    sync::{
-        mpsc::{sync_channel, Receiver, SyncSender},
-        Arc, Mutex,
-    },
     error::Error,
+    time::Duration,
 };
 use expect_dialog::ExpectDialog;
 
 pub fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        let monado_instance_dir =
+            monado_instance_dir().ok_or("System does not have a configured config directory.")?;
+        std::process::exit(cli::run(&monado_instance_dir, &args));
+    }
+
     let mut native_options = eframe::NativeOptions::default();
     native_options.min_window_size = Some(egui::Vec2 { x: 512.0, y: 512.0 });
     eframe::run_native(
@@ -33,14 +53,20 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     )?;
     Ok(())
 }
+pub fn monado_instance_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("monado").join("instances"))
+}
+
 pub struct RexApp {
     pub monado_instance_dir: PathBuf,
     pub logging_env_vars: LoggingEnvVars,
+    pub console_settings: ConsoleSettings,
     current_instance: Option<String>,
     instances: FxHashMap<String, MonadoInstance>,
-    pub console: String,
-    pub stdout_sender: Arc<Mutex<SyncSender<String>>>,
-    pub stdout_receiver: Receiver<String>,
+    /// Instances deleted while their monado-service was still stopping.
+    deleting_instances: FxHashSet<String>,
+    instance_name_input: String,
+    egui_ctx: Context,
 }
 impl RexApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
@@ -51,29 +77,48 @@ impl RexApp {
         let visuals = Visuals::dark();
         cc.egui_ctx.set_visuals(visuals);
 
-        let monado_instance_dir = dirs::config_dir().expect_dialog("System does not have a configured config directory.").join("monado").join("instances");
+        let monado_instance_dir = monado_instance_dir().expect_dialog("System does not have a configured config directory.");
         std::fs::create_dir_all(&monado_instance_dir).expect_dialog("Unable to create config directory folders.");
-        let (stdout_sender, stdout_receiver) = sync_channel(64000);
 
         let mut app = RexApp {
             monado_instance_dir,
             logging_env_vars: RexApp::load_tolerant("monado", "logging"),
-            console: String::default(),
-            stdout_sender: Arc::new(Mutex::new(stdout_sender)),
-            stdout_receiver,
+            console_settings: RexApp::load_tolerant("monado", "console"),
             current_instance: None,
             instances: FxHashMap::default(),
+            deleting_instances: FxHashSet::default(),
+            instance_name_input: String::new(),
+            egui_ctx: cc.egui_ctx.clone(),
         };
         let _ = app.load_instances();
         app
     }
 
+    /// Rescans the instance directory, keeping already loaded (and possibly running) instances.
     pub fn load_instances(&mut self) -> Result<(), Box<dyn Error>> {
-        self.instances = FxHashMap::from_iter(
+        let names: FxHashSet<String> = FxHashSet::from_iter(
             std::fs::read_dir(&self.monado_instance_dir)?
-                .filter_map(|d| Some(d.ok()?.file_name().to_str()?.to_string()))
-                .filter_map(|n| Some((n.clone(), MonadoInstance::create_load(self, n).ok()?))),
-        );   
+                .filter_map(|d| d.ok())
+                .filter(|d| d.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|d| Some(d.file_name().to_str()?.to_string())),
+        );
+        // Dropping an instance waits for its children, keep running ones until they are stopped.
+        self.instances
+            .retain(|name, instance| names.contains(name) || instance.has_children());
+        for name in names {
+            if self.instances.contains_key(&name) {
+                continue;
+            }
+            if let Ok(mut instance) = MonadoInstance::create_load(&self.monado_instance_dir, name.clone()) {
+                instance.stale_socket = instance.find_stale_socket();
+                self.insert_instance(name, instance);
+            }
+        }
+        if let Some(current) = &self.current_instance {
+            if !self.instances.contains_key(current) {
+                self.current_instance = None;
+            }
+        }
 
         Ok(())
     }
@@ -93,6 +138,15 @@ impl RexApp {
 
             _ => {}
         }
+        if let Err(err) = confy::store("monado", "console", self.console_settings) {
+            println!("Error saving global console config: {}", err);
+        }
+    }
+
+    /// Adds a loaded instance with a console of its own.
+    pub fn insert_instance(&mut self, name: String, mut instance: MonadoInstance) {
+        instance.console = Console::new(self.console_settings.max_lines, Some(self.egui_ctx.clone()));
+        self.instances.insert(name, instance);
     }
 
     pub fn current_instance(&mut self) -> Option<&mut MonadoInstance> {
@@ -134,13 +188,34 @@ impl RexApp {
 
 }
 impl App for RexApp {
-    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
         control_panel::update(self, ctx);
         log_options::update(self, ctx);
-
+        instance_manager::update(self, ctx);
+
+        let logging_env_vars = self.logging_env_vars;
+        for instance in self.instances.values_mut() {
+            instance.console.drain();
+            instance.poll_monado(&logging_env_vars);
+            let clients_waiting = instance.poll_clients();
+            // Nothing else wakes the UI up to notice readiness, escalate a stop, restart, start a
+            // client or notice an exit.
+            let status = instance.status();
+            if clients_waiting
+                || matches!(
+                    status,
+                    ServiceStatus::Starting | ServiceStatus::Stopping | ServiceStatus::Restarting
+                )
+            {
+                ctx.request_repaint_after(Duration::from_millis(100));
+            } else if instance.child.is_some() || instance.clients.iter().any(|c| c.child.is_some()) {
+                ctx.request_repaint_after(Duration::from_millis(500));
+            }
+        }
         if let Some(instance) = self.current_instance() {
-            instance.update(ctx);
+            instance.update(ctx, &logging_env_vars);
         }
+        self.poll_deletes();
 
         let style = Style {
             visuals: Visuals {
@@ -152,7 +227,7 @@ impl App for RexApp {
         egui::CentralPanel::default()
             .frame(egui::Frame::central_panel(&style))
             .show(ctx, |ui| {
-                console::update(self, ui, frame);
+                console::update(self, ui);
             });
     }
 }
@@ -0,0 +1,607 @@
+use crate::{env_var::EnvSink, instance::MonadoInstance};
+use egui::{Color32, ComboBox, Context, DragValue, Ui};
+use native_dialog::MessageDialog;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "config_v0.json";
+const DEFAULT_REMOTE_PORT: u16 = 4242;
+const DEFAULT_INPUT_NAME: &str = "XRT_INPUT_GENERIC_TRACKER_POSE";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct MonadoConfigSettings {
+    /// Give monado-service a config_v0.json of its own instead of the user's.
+    pub per_instance: bool,
+}
+
+/// Monado's `config_v0.json`. Keys rex doesn't know, like calibration, are kept as they were.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MonadoConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<ActiveConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking: Option<TrackingConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteConfig>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Which of the sections Monado uses to set up its devices.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActiveConfig {
+    None,
+    Tracking,
+    Remote,
+}
+impl ActiveConfig {
+    pub const ALL: [ActiveConfig; 3] = [
+        ActiveConfig::None,
+        ActiveConfig::Tracking,
+        ActiveConfig::Remote,
+    ];
+}
+impl fmt::Display for ActiveConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActiveConfig::None => write!(f, "None"),
+            ActiveConfig::Tracking => write!(f, "Tracking"),
+            ActiveConfig::Remote => write!(f, "Remote"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrackingConfig {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tracking_overrides: Vec<TrackingOverride>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Makes one device follow the pose of another, e.g. a controller strapped to a tracker.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrackingOverride {
+    #[serde(default)]
+    pub target_device_serial: String,
+    #[serde(default)]
+    pub tracker_device_serial: String,
+    #[serde(rename = "type", default)]
+    pub kind: OverrideType,
+    #[serde(default)]
+    pub offset: Pose,
+    #[serde(default = "default_input_name")]
+    pub xrt_input_name: String,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+impl Default for TrackingOverride {
+    fn default() -> Self {
+        TrackingOverride {
+            target_device_serial: String::new(),
+            tracker_device_serial: String::new(),
+            kind: OverrideType::default(),
+            offset: Pose::default(),
+            xrt_input_name: default_input_name(),
+            other: Map::new(),
+        }
+    }
+}
+
+fn default_input_name() -> String {
+    DEFAULT_INPUT_NAME.to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideType {
+    /// The target takes the tracker's pose.
+    #[default]
+    Direct,
+    /// The target's own pose is offset by the tracker's.
+    Attached,
+}
+impl OverrideType {
+    pub const ALL: [OverrideType; 2] = [OverrideType::Direct, OverrideType::Attached];
+}
+impl fmt::Display for OverrideType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverrideType::Direct => write!(f, "Direct"),
+            OverrideType::Attached => write!(f, "Attached"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Pose {
+    pub orientation: Quat,
+    pub position: Vec3,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Quat {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+impl Default for Quat {
+    fn default() -> Self {
+        Quat {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RemoteConfig {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default = "default_remote_port")]
+    pub port: u16,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig {
+            version: 0,
+            port: DEFAULT_REMOTE_PORT,
+            other: Map::new(),
+        }
+    }
+}
+
+fn default_remote_port() -> u16 {
+    DEFAULT_REMOTE_PORT
+}
+
+impl MonadoConfig {
+    /// A missing file is an empty config, Monado creates it the same way.
+    pub fn load(path: &Path) -> Result<MonadoConfig, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(text) if text.trim().is_empty() => Ok(MonadoConfig::default()),
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(MonadoConfig::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Things that parse fine but won't do what was likely meant.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.active == Some(ActiveConfig::Tracking) && self.tracking.is_none() {
+            problems.push("Tracking is active, but there is no tracking section.".to_string());
+        }
+        if self.active == Some(ActiveConfig::Remote) && self.remote.is_none() {
+            problems.push(format!(
+                "Remote is active without a remote section, port {} is used.",
+                DEFAULT_REMOTE_PORT
+            ));
+        }
+        let overrides = self.tracking.iter().flat_map(|t| &t.tracking_overrides);
+        for (i, tracking_override) in overrides.enumerate() {
+            if tracking_override.target_device_serial.trim().is_empty()
+                || tracking_override.tracker_device_serial.trim().is_empty()
+            {
+                problems.push(format!("Override {} is missing a device serial.", i + 1));
+            }
+            let q = tracking_override.offset.orientation;
+            let length = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+            if (length - 1.0).abs() > 0.001 {
+                problems.push(format!(
+                    "The orientation of override {} isn't normalized, its length is {:.3}.",
+                    i + 1,
+                    length
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// The config file being edited and whether it differs from what is on disk.
+#[derive(Debug)]
+pub struct ConfigEditor {
+    path: PathBuf,
+    config: Result<MonadoConfig, String>,
+    modified: bool,
+}
+impl ConfigEditor {
+    fn load(path: PathBuf) -> ConfigEditor {
+        ConfigEditor {
+            config: MonadoConfig::load(&path).map_err(|err| err.to_string()),
+            path,
+            modified: false,
+        }
+    }
+}
+
+impl MonadoInstance {
+    /// Where monado-service looks for its config when `per_instance` is set.
+    pub fn config_home(&self) -> Option<PathBuf> {
+        self.monado_config.per_instance.then(|| self.config_dir())
+    }
+
+    /// The config_v0.json this instance's monado-service reads.
+    pub fn monado_config_path(&self) -> Option<PathBuf> {
+        let config_home = self.config_home().or_else(dirs::config_dir)?;
+        Some(config_home.join("monado").join(CONFIG_FILE))
+    }
+
+    pub fn set_config_vars<C: EnvSink>(&self, command: C) -> C {
+        match self.config_home() {
+            Some(config_home) => command.env("XDG_CONFIG_HOME", config_home.to_string_lossy()),
+            None => command,
+        }
+    }
+}
+
+/// Copies the user's config_v0.json to the instance, unless the instance has one already.
+fn copy_user_config(instance_path: &Path) -> Result<(), Box<dyn Error>> {
+    let Some(user_path) = dirs::config_dir().map(|d| d.join("monado").join(CONFIG_FILE)) else {
+        return Ok(());
+    };
+    if instance_path.exists() || !user_path.is_file() {
+        return Ok(());
+    }
+    if let Some(dir) = instance_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(user_path, instance_path)?;
+    Ok(())
+}
+
+fn show_error(text: &str, err: &dyn Error) {
+    println!("Monado config error: {}", err);
+
+    MessageDialog::new()
+        .set_title("Monado Config Error")
+        .set_text(&format!("{}\nError:\n{}", text, err))
+        .set_type(native_dialog::MessageType::Error)
+        .show_alert()
+        .expect("Error creating dialog window for Monado config error");
+}
+
+pub fn update(inst: &mut MonadoInstance, ctx: &Context) -> bool {
+    let mut changed = false;
+    egui::Window::new("Monado Config")
+        .default_open(false)
+        .collapsible(true)
+        .show(ctx, |ui| {
+            if ui
+                .checkbox(&mut inst.monado_config.per_instance, "Per-Instance Config")
+                .on_hover_text(
+                    "Run monado-service with XDG_CONFIG_HOME in the instance's directory. \
+                     It starts as a copy of your config_v0.json, other configs Monado reads \
+                     from there, like libsurvive's calibration, aren't copied.",
+                )
+                .changed()
+            {
+                changed = true;
+                if let Some(path) = inst
+                    .monado_config_path()
+                    .filter(|_| inst.monado_config.per_instance)
+                {
+                    if let Err(err) = copy_user_config(&path) {
+                        show_error("Unable to copy your config_v0.json to the instance.", &*err);
+                    }
+                }
+            }
+            let Some(path) = inst.monado_config_path() else {
+                ui.colored_label(Color32::LIGHT_RED, "No config directory.");
+                return;
+            };
+            if inst.config_editor.as_ref().map(|e| &e.path) != Some(&path) {
+                inst.config_editor = Some(ConfigEditor::load(path.clone()));
+            }
+            let editor = inst.config_editor.as_mut().unwrap();
+            ui.label(path.display().to_string());
+            ui.separator();
+
+            let mut reload = false;
+            match &mut editor.config {
+                Ok(config) => {
+                    editor.modified |= edit(config, ui);
+                    for problem in config.problems() {
+                        ui.colored_label(Color32::YELLOW, problem);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(editor.modified, egui::Button::new("Save"))
+                            .on_hover_text("monado-service reads it when it starts.")
+                            .clicked()
+                        {
+                            match config.save(&editor.path) {
+                                Ok(()) => editor.modified = false,
+                                Err(err) => show_error("Unable to save config_v0.json.", &*err),
+                            }
+                        }
+                        reload = ui
+                            .add_enabled(editor.modified, egui::Button::new("Discard Changes"))
+                            .clicked();
+                    });
+                }
+                Err(err) => {
+                    ui.colored_label(
+                        Color32::LIGHT_RED,
+                        format!("Invalid config_v0.json: {}", err),
+                    );
+                    reload = ui.button("Reload").clicked();
+                }
+            }
+            if reload {
+                *editor = ConfigEditor::load(path);
+            }
+        });
+    changed
+}
+
+/// The typed sections, other keys are listed but left alone.
+fn edit(config: &mut MonadoConfig, ui: &mut Ui) -> bool {
+    let mut changed = false;
+    egui::Grid::new("monado_config_active")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Active");
+            let text = config.active.map_or("Unset".to_string(), |a| a.to_string());
+            ComboBox::from_id_source("monado_config_active")
+                .selected_text(text)
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut config.active, None, "Unset")
+                        .changed();
+                    for active in ActiveConfig::ALL {
+                        changed |= ui
+                            .selectable_value(&mut config.active, Some(active), active.to_string())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+        });
+
+    let mut remove_tracking = false;
+    ui.collapsing("Tracking", |ui| match &mut config.tracking {
+        Some(tracking) => {
+            let mut remove = None;
+            for (i, tracking_override) in tracking.tracking_overrides.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    egui::CollapsingHeader::new(format!("Override {}", i + 1))
+                        .id_source("tracking_override")
+                        .show(ui, |ui| {
+                            changed |= override_settings(tracking_override, ui);
+                            if ui.button("Remove Override").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                });
+            }
+            if let Some(i) = remove {
+                tracking.tracking_overrides.remove(i);
+                changed = true;
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add Override").clicked() {
+                    tracking
+                        .tracking_overrides
+                        .push(TrackingOverride::default());
+                    changed = true;
+                }
+                remove_tracking = ui.button("Remove Section").clicked();
+            });
+        }
+        None => {
+            if ui.button("Add Tracking Section").clicked() {
+                config.tracking = Some(TrackingConfig::default());
+                changed = true;
+            }
+        }
+    });
+
+    if remove_tracking {
+        config.tracking = None;
+        changed = true;
+    }
+
+    let mut remove_remote = false;
+    ui.collapsing("Remote", |ui| match &mut config.remote {
+        Some(remote) => {
+            ui.horizontal(|ui| {
+                ui.label("Port");
+                changed |= ui
+                    .add(DragValue::new(&mut remote.port).clamp_range(1..=u16::MAX))
+                    .changed();
+                remove_remote = ui.button("Remove Section").clicked();
+            });
+        }
+        None => {
+            if ui.button("Add Remote Section").clicked() {
+                config.remote = Some(RemoteConfig::default());
+                changed = true;
+            }
+        }
+    });
+
+    if remove_remote {
+        config.remote = None;
+        changed = true;
+    }
+
+    if !config.other.is_empty() {
+        let keys: Vec<&str> = config.other.keys().map(String::as_str).collect();
+        ui.label(format!("Also kept as is: {}", keys.join(", ")));
+    }
+    changed
+}
+
+fn override_settings(tracking_override: &mut TrackingOverride, ui: &mut Ui) -> bool {
+    let mut changed = false;
+    egui::Grid::new("tracking_override")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Target Serial")
+                .on_hover_text("The device that gets its pose from the tracker.");
+            changed |= ui
+                .text_edit_singleline(&mut tracking_override.target_device_serial)
+                .changed();
+            ui.end_row();
+
+            ui.label("Tracker Serial");
+            changed |= ui
+                .text_edit_singleline(&mut tracking_override.tracker_device_serial)
+                .changed();
+            ui.end_row();
+
+            ui.label("Type");
+            ComboBox::from_id_source("override_type")
+                .selected_text(tracking_override.kind.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in OverrideType::ALL {
+                        changed |= ui
+                            .selectable_value(&mut tracking_override.kind, kind, kind.to_string())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Input");
+            changed |= ui
+                .text_edit_singleline(&mut tracking_override.xrt_input_name)
+                .changed();
+            ui.end_row();
+
+            let position = &mut tracking_override.offset.position;
+            ui.label("Position").on_hover_text("Offset in meters.");
+            ui.horizontal(|ui| {
+                for value in [&mut position.x, &mut position.y, &mut position.z] {
+                    changed |= ui.add(DragValue::new(value).speed(0.001)).changed();
+                }
+            });
+            ui.end_row();
+
+            let orientation = &mut tracking_override.offset.orientation;
+            ui.label("Orientation")
+                .on_hover_text("Quaternion as x, y, z, w.");
+            ui.horizontal(|ui| {
+                for value in [
+                    &mut orientation.x,
+                    &mut orientation.y,
+                    &mut orientation.z,
+                    &mut orientation.w,
+                ] {
+                    changed |= ui
+                        .add(DragValue::new(value).speed(0.001).clamp_range(-1.0..=1.0))
+                        .changed();
+                }
+            });
+            ui.end_row();
+        });
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+        "active": "tracking",
+        "tracking": {
+            "version": 0,
+            "camera_name": "Valve Index",
+            "tracking_overrides": [
+                {
+                    "target_device_serial": "LHR-AAAAAAAA",
+                    "tracker_device_serial": "LHR-BBBBBBBB",
+                    "type": "attached",
+                    "offset": {
+                        "orientation": { "x": 0.0, "y": 0.0, "z": 0.0, "w": 2.0 },
+                        "position": { "x": 0.1, "y": 0.0, "z": -0.05 }
+                    },
+                    "extra": true
+                }
+            ]
+        },
+        "calibration": { "camera": [1, 2, 3] }
+    }"#;
+
+    #[test]
+    fn keeps_unknown_keys() {
+        let mut config: MonadoConfig = serde_json::from_str(CONFIG).unwrap();
+        assert_eq!(config.active, Some(ActiveConfig::Tracking));
+        let tracking = config.tracking.as_ref().unwrap();
+        let tracking_override = &tracking.tracking_overrides[0];
+        assert_eq!(tracking_override.kind, OverrideType::Attached);
+        assert_eq!(tracking_override.xrt_input_name, DEFAULT_INPUT_NAME);
+        assert_eq!(tracking_override.offset.position.z, -0.05);
+        assert_eq!(
+            config.problems(),
+            ["The orientation of override 1 isn't normalized, its length is 2.000."]
+        );
+
+        config.remote = Some(RemoteConfig::default());
+        config.active = Some(ActiveConfig::Remote);
+        let saved: Value = serde_json::to_value(&config).unwrap();
+        let mut expected: Value = serde_json::from_str(CONFIG).unwrap();
+        expected["active"] = "remote".into();
+        expected["remote"] = serde_json::json!({ "version": 0, "port": 4242 });
+        expected["tracking"]["tracking_overrides"][0]["xrt_input_name"] = DEFAULT_INPUT_NAME.into();
+        assert_eq!(saved, expected);
+    }
+
+    #[test]
+    fn loads_missing_and_reports_invalid() {
+        let dir = std::env::temp_dir().join(format!("rex-monado-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("monado").join(CONFIG_FILE);
+        assert_eq!(MonadoConfig::load(&path).unwrap(), MonadoConfig::default());
+
+        let config = MonadoConfig {
+            active: Some(ActiveConfig::Tracking),
+            ..MonadoConfig::default()
+        };
+        config.save(&path).unwrap();
+        assert_eq!(MonadoConfig::load(&path).unwrap(), config);
+        assert_eq!(
+            config.problems(),
+            ["Tracking is active, but there is no tracking section."]
+        );
+
+        fs::write(&path, "{ \"active\": \"lighthouse\" }").unwrap();
+        assert!(MonadoConfig::load(&path).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
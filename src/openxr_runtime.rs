@@ -0,0 +1,402 @@
+use crate::{
+    env_var::{EnvList, EnvSink},
+    instance::MonadoInstance,
+    service::find_executable,
+};
+use egui::{Color32, Context};
+use native_dialog::MessageDialog;
+use std::{
+    error::Error,
+    fmt, fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+const MANIFEST_NAME: &str = "openxr_monado.json";
+const SYSTEM_MANIFEST_DIRS: [&str; 3] = [
+    "/usr/local/share/openxr/1",
+    "/usr/share/openxr/1",
+    "/etc/xdg/openxr/1",
+];
+
+/// The manifest the OpenXR loader uses when `XR_RUNTIME_JSON` isn't set.
+pub fn active_runtime_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("openxr")
+            .join("1")
+            .join("active_runtime.json"),
+    )
+}
+
+/// Where the active runtime rex replaced is kept until it is restored.
+fn backup_path(active: &Path) -> PathBuf {
+    active.with_file_name("active_runtime.json.rex-backup")
+}
+
+/// Marks that there was no active runtime before rex set one, restoring removes rex's.
+fn missing_marker_path(active: &Path) -> PathBuf {
+    active.with_file_name("active_runtime.json.rex-missing")
+}
+
+fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+fn system_manifest() -> Option<PathBuf> {
+    SYSTEM_MANIFEST_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join(MANIFEST_NAME))
+        .find(|path| path.is_file())
+}
+
+/// The runtime library a manifest points at, relative paths are resolved against the manifest.
+fn library_path(manifest: &Path) -> Result<String, Box<dyn Error>> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(manifest)?)?;
+    let library = json
+        .pointer("/runtime/library_path")
+        .and_then(|l| l.as_str())
+        .ok_or("The manifest has no runtime.library_path.")?;
+    Ok(resolve_library_path(manifest, library))
+}
+
+/// A bare library name is left for the dynamic linker to find, like the loader does.
+fn resolve_library_path(manifest: &Path, library: &str) -> String {
+    if library.contains('/') && Path::new(library).is_relative() {
+        let dir = manifest.parent().unwrap_or(Path::new("/"));
+        dir.join(library).to_string_lossy().into_owned()
+    } else {
+        library.to_string()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ActiveRuntime {
+    Missing,
+    /// The link's target as written, which may be relative to the link, and the manifest it
+    /// ends up at.
+    Symlink(PathBuf, Option<PathBuf>),
+    /// A copied manifest, described by the library it loads.
+    File(Option<String>),
+}
+impl ActiveRuntime {
+    pub fn read(path: &Path) -> Self {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return ActiveRuntime::Missing;
+        };
+        if metadata.file_type().is_symlink() {
+            match fs::read_link(path) {
+                Ok(target) => ActiveRuntime::Symlink(target, fs::canonicalize(path).ok()),
+                Err(_) => ActiveRuntime::Missing,
+            }
+        } else {
+            ActiveRuntime::File(library_path(path).ok())
+        }
+    }
+
+    /// Whether the loader would end up loading the runtime `manifest` describes.
+    pub fn is(&self, manifest: &Path) -> bool {
+        match self {
+            ActiveRuntime::Missing => false,
+            ActiveRuntime::Symlink(_, resolved) => {
+                resolved.is_some()
+                    && resolved.as_deref() == fs::canonicalize(manifest).ok().as_deref()
+            }
+            ActiveRuntime::File(library) => {
+                library.is_some() && library.as_deref() == library_path(manifest).ok().as_deref()
+            }
+        }
+    }
+}
+impl fmt::Display for ActiveRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActiveRuntime::Missing => write!(f, "None"),
+            ActiveRuntime::Symlink(target, _) => write!(f, "Symlink to {}", target.display()),
+            ActiveRuntime::File(Some(library)) => write!(f, "File loading {}", library),
+            ActiveRuntime::File(None) => write!(f, "File without a runtime library"),
+        }
+    }
+}
+
+/// Makes `manifest` the active runtime, keeping whatever was active before rex first changed it.
+///
+/// A symlink stays a symlink. A regular file is replaced by a copy of the manifest with its
+/// library path made absolute, since it no longer sits next to the manifest.
+pub fn activate(active: &Path, manifest: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = active.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let existing = ActiveRuntime::read(active);
+    let saved = exists(&backup_path(active)) || exists(&missing_marker_path(active));
+    if existing == ActiveRuntime::Missing {
+        if !saved {
+            fs::write(missing_marker_path(active), "")?;
+        }
+    } else if !saved {
+        fs::rename(active, backup_path(active))?;
+    } else {
+        fs::remove_file(active)?;
+    }
+
+    match existing {
+        ActiveRuntime::File(_) => {
+            let mut json: serde_json::Value = serde_json::from_str(&fs::read_to_string(manifest)?)?;
+            if let Some(library) = json.pointer_mut("/runtime/library_path") {
+                if let Some(path) = library.as_str() {
+                    *library = resolve_library_path(manifest, path).into();
+                }
+            }
+            fs::write(active, serde_json::to_string_pretty(&json)?)?;
+        }
+        ActiveRuntime::Missing | ActiveRuntime::Symlink(..) => symlink(manifest, active)?,
+    }
+    Ok(())
+}
+
+pub fn has_backup(active: &Path) -> bool {
+    exists(&backup_path(active)) || exists(&missing_marker_path(active))
+}
+
+/// Puts back the active runtime from before rex changed it.
+pub fn restore(active: &Path) -> Result<(), Box<dyn Error>> {
+    let backup = backup_path(active);
+    let marker = missing_marker_path(active);
+    if !exists(&backup) && !exists(&marker) {
+        return Err("There is no previous active runtime to restore.".into());
+    }
+    if exists(active) {
+        fs::remove_file(active)?;
+    }
+    if exists(&backup) {
+        fs::rename(&backup, active)?;
+    }
+    if exists(&marker) {
+        fs::remove_file(&marker)?;
+    }
+    Ok(())
+}
+
+impl MonadoInstance {
+    /// The manifest installed next to the monado-service this instance runs, or the system one.
+    pub fn runtime_manifest(&self) -> Option<PathBuf> {
+        let service = find_executable(&self.monado_service()).ok()?;
+        let prefix = service.parent()?.parent()?;
+        let manifest = prefix
+            .join("share")
+            .join("openxr")
+            .join("1")
+            .join(MANIFEST_NAME);
+        if manifest.is_file() {
+            Some(manifest)
+        } else {
+            system_manifest()
+        }
+    }
+
+    /// What an OpenXR app needs to use this instance without changing the active runtime.
+    pub fn client_env(&self) -> EnvList {
+        let mut env = self.set_runtime_vars(EnvList::default());
+        if let Some(manifest) = self.runtime_manifest() {
+            env = env.env("XR_RUNTIME_JSON", manifest.to_string_lossy());
+        }
+        env
+    }
+}
+
+pub fn update(inst: &mut MonadoInstance, ctx: &Context) {
+    egui::Window::new("OpenXR Runtime")
+        .default_open(false)
+        .collapsible(true)
+        .show(ctx, |ui| {
+            let Some(active_path) = active_runtime_path() else {
+                ui.colored_label(Color32::LIGHT_RED, "No config directory.");
+                return;
+            };
+            let active = ActiveRuntime::read(&active_path);
+            let manifest = inst.runtime_manifest();
+
+            egui::Grid::new("openxr_runtime")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Active");
+                    ui.label(active.to_string())
+                        .on_hover_text(active_path.display().to_string());
+                    ui.end_row();
+
+                    ui.label("This Instance");
+                    match &manifest {
+                        Some(manifest) if active.is(manifest) => {
+                            ui.colored_label(Color32::LIGHT_GREEN, manifest.display().to_string());
+                        }
+                        Some(manifest) => {
+                            ui.label(manifest.display().to_string());
+                        }
+                        None => {
+                            ui.colored_label(Color32::LIGHT_RED, "No openxr_monado.json found.");
+                        }
+                    }
+                    ui.end_row();
+                });
+
+            let result = ui
+                .horizontal(|ui| {
+                    let can_activate = manifest.as_ref().is_some_and(|m| !active.is(m));
+                    if ui
+                        .add_enabled(can_activate, egui::Button::new("Make Active"))
+                        .clicked()
+                    {
+                        return manifest
+                            .as_deref()
+                            .map(|manifest| activate(&active_path, manifest));
+                    }
+                    if ui
+                        .add_enabled(
+                            has_backup(&active_path),
+                            egui::Button::new("Restore Previous"),
+                        )
+                        .clicked()
+                    {
+                        return Some(restore(&active_path));
+                    }
+                    None
+                })
+                .inner;
+            if let Some(Err(err)) = result {
+                println!("OpenXR runtime error: {}", err);
+
+                MessageDialog::new()
+                    .set_title("OpenXR Runtime Error")
+                    .set_text(&format!(
+                        "Unable to change the active runtime.\nError:\n{}",
+                        err
+                    ))
+                    .set_type(native_dialog::MessageType::Error)
+                    .show_alert()
+                    .expect("Error creating dialog window for OpenXR runtime error");
+            }
+
+            ui.separator();
+            ui.label("Or start apps with this instance without changing the active runtime:");
+            let env = inst
+                .client_env()
+                .0
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, shell_words::quote(value)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            ui.horizontal(|ui| {
+                ui.monospace(&env);
+                if ui.small_button("Copy").clicked() {
+                    ui.output_mut(|o| o.copied_text = env.clone());
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manifest with a relative library path and an active runtime path next to it.
+    fn setup(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rex-openxr-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("share")).unwrap();
+        let manifest = dir.join("share").join(MANIFEST_NAME);
+        fs::write(
+            &manifest,
+            r#"{"file_format_version": "1.0.0", "runtime": {"library_path": "../lib/libopenxr_monado.so"}}"#,
+        )
+        .unwrap();
+        let active = dir.join("config").join("active_runtime.json");
+        (dir, manifest, active)
+    }
+
+    #[test]
+    fn recognizes_active_runtime() {
+        let (dir, manifest, active) = setup("is");
+        let other = dir.join("share").join("other.json");
+        fs::write(&other, r#"{"runtime": {"library_path": "libother.so"}}"#).unwrap();
+        fs::create_dir_all(active.parent().unwrap()).unwrap();
+
+        assert_eq!(ActiveRuntime::read(&active), ActiveRuntime::Missing);
+        assert!(!ActiveRuntime::read(&active).is(&manifest));
+
+        symlink(&manifest, &active).unwrap();
+        assert!(ActiveRuntime::read(&active).is(&manifest));
+        assert!(!ActiveRuntime::read(&active).is(&other));
+
+        // Relative to the link, not to the working directory.
+        fs::remove_file(&active).unwrap();
+        symlink(Path::new("../share").join(MANIFEST_NAME), &active).unwrap();
+        let runtime = ActiveRuntime::read(&active);
+        assert_eq!(
+            runtime.to_string(),
+            format!("Symlink to ../share/{}", MANIFEST_NAME)
+        );
+        assert!(runtime.is(&manifest));
+        assert!(!runtime.is(&other));
+
+        // A copy is compared by the library it loads, resolved against the original manifest.
+        fs::remove_file(&active).unwrap();
+        let library = dir.join("share/../lib/libopenxr_monado.so");
+        fs::write(
+            &active,
+            format!(
+                r#"{{"runtime": {{"library_path": "{}"}}}}"#,
+                library.display()
+            ),
+        )
+        .unwrap();
+        let runtime = ActiveRuntime::read(&active);
+        assert_eq!(
+            runtime,
+            ActiveRuntime::File(Some(library.to_string_lossy().into_owned()))
+        );
+        assert!(runtime.is(&manifest));
+        assert!(!runtime.is(&other));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn activates_and_restores() {
+        let (dir, manifest, active) = setup("activate");
+
+        // Nothing was active, restoring removes rex's link again.
+        activate(&active, &manifest).unwrap();
+        assert!(ActiveRuntime::read(&active).is(&manifest));
+        assert!(has_backup(&active));
+        restore(&active).unwrap();
+        assert_eq!(ActiveRuntime::read(&active), ActiveRuntime::Missing);
+        assert!(!has_backup(&active));
+        assert!(restore(&active).is_err());
+
+        // A symlink is kept, even when activating twice.
+        let previous = Path::new("/usr/share/openxr/1/openxr_previous.json");
+        symlink(previous, &active).unwrap();
+        activate(&active, &manifest).unwrap();
+        activate(&active, &manifest).unwrap();
+        assert!(fs::symlink_metadata(&active)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(ActiveRuntime::read(&active).is(&manifest));
+        restore(&active).unwrap();
+        assert_eq!(fs::read_link(&active).unwrap(), previous);
+        assert!(!has_backup(&active));
+
+        // A regular file is replaced by a copy with an absolute library path.
+        fs::remove_file(&active).unwrap();
+        let previous = r#"{"runtime": {"library_path": "/usr/lib/libprevious.so"}}"#;
+        fs::write(&active, previous).unwrap();
+        activate(&active, &manifest).unwrap();
+        assert!(fs::symlink_metadata(&active).unwrap().file_type().is_file());
+        assert!(ActiveRuntime::read(&active).is(&manifest));
+        restore(&active).unwrap();
+        assert_eq!(fs::read_to_string(&active).unwrap(), previous);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,680 @@
+use crate::instance::MonadoInstance;
+use egui::{Color32, ComboBox, Context, DragValue};
+use nix::sys::signal::Signal;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    io::ErrorKind,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use subprocess::{ExitStatus, PopenError};
+
+/// How monado-service is run and stopped.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServiceSettings {
+    pub executable: ServiceExecutable,
+    /// Used when `executable` is `Custom`.
+    pub custom_executable: String,
+    /// Extra arguments, split like a shell would.
+    pub args: String,
+    /// Empty to inherit rex's.
+    pub working_dir: String,
+    /// Command monado-service is run through, e.g. `gdb -ex run --args`.
+    pub wrapper: String,
+    pub stop_signal: StopSignal,
+    /// Seconds to wait after `stop_signal` before monado-service is killed.
+    pub stop_timeout: f32,
+    /// Give monado-service its own XDG_RUNTIME_DIR, so instances can run side by side.
+    pub separate_runtime_dir: bool,
+    /// Wait for monado-service to create its IPC socket before it counts as ready.
+    pub ready_socket: bool,
+    /// Regex an output line has to match before monado-service counts as ready, empty to not wait for one.
+    pub ready_pattern: String,
+    /// Which exits rex didn't ask for start monado-service again.
+    pub restart: RestartPolicy,
+    /// Seconds before the first restart, doubled for every further one within `restart_window`.
+    pub restart_delay: f32,
+    /// Restarts allowed within `restart_window` before rex gives up.
+    pub max_restarts: u32,
+    /// Seconds restarts are counted over.
+    pub restart_window: f32,
+    /// Session logs kept in the instance's log directory, 0 to not write any.
+    pub keep_logs: u32,
+}
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        ServiceSettings {
+            executable: ServiceExecutable::default(),
+            custom_executable: String::new(),
+            args: String::new(),
+            working_dir: String::new(),
+            wrapper: String::new(),
+            stop_signal: StopSignal::default(),
+            stop_timeout: 5.0,
+            separate_runtime_dir: true,
+            ready_socket: true,
+            ready_pattern: String::new(),
+            restart: RestartPolicy::default(),
+            restart_delay: 1.0,
+            max_restarts: 5,
+            restart_window: 300.0,
+            keep_logs: 20,
+        }
+    }
+}
+impl ServiceSettings {
+    pub fn stop_timeout(&self) -> Duration {
+        Duration::from_secs_f32(self.stop_timeout.max(0.0))
+    }
+
+    pub fn restart_window(&self) -> Duration {
+        Duration::from_secs_f32(self.restart_window.max(0.0))
+    }
+
+    /// How long to wait before a restart when `previous` restarts happened within the window.
+    pub fn restart_backoff(&self, previous: usize) -> Duration {
+        let delay = self.restart_delay.max(0.0) * 2f32.powi(previous.min(16) as i32);
+        Duration::from_secs_f32(delay.min(MAX_RESTART_DELAY))
+    }
+
+    /// What the restart policy does after `status`, given when the previous restarts happened.
+    pub fn plan_restart(
+        &self,
+        status: ServiceStatus,
+        restarts: &[Instant],
+        now: Instant,
+    ) -> RestartPlan {
+        if !self.restart.restarts_after(status) {
+            return RestartPlan::Stay;
+        }
+        let window = self.restart_window();
+        let recent = restarts
+            .iter()
+            .filter(|&&at| now.saturating_duration_since(at) < window)
+            .count();
+        if recent >= self.max_restarts as usize {
+            RestartPlan::GiveUp(recent)
+        } else {
+            RestartPlan::After(self.restart_backoff(recent))
+        }
+    }
+
+    pub fn ready_regex(&self) -> Result<Option<Regex>, StartError> {
+        let pattern = self.ready_pattern.trim();
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        Regex::new(pattern)
+            .map(Some)
+            .map_err(|err| StartError::InvalidSettings(format!("Invalid ready pattern: {}", err)))
+    }
+
+    pub fn update(inst: &mut MonadoInstance, ctx: &Context) -> bool {
+        let mut changed = false;
+        egui::Window::new("Service")
+            .default_open(false)
+            .collapsible(true)
+            .show(ctx, |ui| {
+                egui::Grid::new("service_settings")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        let settings = &mut inst.service_settings;
+
+                        ui.label("Executable");
+                        ComboBox::from_id_source("service_executable")
+                            .selected_text(settings.executable.to_string())
+                            .show_ui(ui, |ui| {
+                                for executable in ServiceExecutable::ALL {
+                                    changed |= ui
+                                        .selectable_value(
+                                            &mut settings.executable,
+                                            executable,
+                                            executable.to_string(),
+                                        )
+                                        .changed();
+                                }
+                            });
+                        ui.end_row();
+
+                        if settings.executable == ServiceExecutable::Custom {
+                            ui.label("Path");
+                            changed |= ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut settings.custom_executable)
+                                        .hint_text("/path/to/monado-service"),
+                                )
+                                .changed();
+                            ui.end_row();
+                        }
+
+                        ui.label("Arguments");
+                        changed |= ui.text_edit_singleline(&mut settings.args).changed();
+                        ui.end_row();
+
+                        ui.label("Working Dir");
+                        changed |= ui
+                            .add(
+                                egui::TextEdit::singleline(&mut settings.working_dir)
+                                    .hint_text("Inherited from rex"),
+                            )
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Wrapper");
+                        changed |= ui
+                            .add(
+                                egui::TextEdit::singleline(&mut settings.wrapper)
+                                    .hint_text("e.g. gdb -ex run --args"),
+                            )
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Stop Signal");
+                        ComboBox::from_id_source("stop_signal")
+                            .selected_text(settings.stop_signal.to_string())
+                            .show_ui(ui, |ui| {
+                                for signal in [StopSignal::Interrupt, StopSignal::Terminate] {
+                                    changed |= ui
+                                        .selectable_value(
+                                            &mut settings.stop_signal,
+                                            signal,
+                                            signal.to_string(),
+                                        )
+                                        .changed();
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Stop Timeout");
+                        changed |= ui
+                            .add(
+                                DragValue::new(&mut settings.stop_timeout)
+                                    .clamp_range(0.0..=120.0)
+                                    .speed(0.1)
+                                    .suffix(" s"),
+                            )
+                            .on_hover_text(
+                                "How long monado-service gets to clean up before it is killed.",
+                            )
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Runtime Dir");
+                        changed |= ui
+                            .checkbox(&mut settings.separate_runtime_dir, "Separate")
+                            .on_hover_text(
+                                "Monado creates its IPC socket in XDG_RUNTIME_DIR, a separate one \
+                                 lets several instances run at once. OpenXR apps started outside \
+                                 rex need the same XDG_RUNTIME_DIR to connect.",
+                            )
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Session Logs");
+                        changed |= ui
+                            .add(DragValue::new(&mut settings.keep_logs).clamp_range(0..=1000))
+                            .on_hover_text("How many runs to keep on disk, 0 to not save any.")
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("Ready When");
+                        changed |= ui
+                            .checkbox(&mut settings.ready_socket, "IPC socket exists")
+                            .on_hover_text("monado_comp_ipc in monado-service's XDG_RUNTIME_DIR.")
+                            .changed();
+                        ui.end_row();
+
+                        ui.label("");
+                        changed |= ui
+                            .add(
+                                egui::TextEdit::singleline(&mut settings.ready_pattern)
+                                    .hint_text("and a line matches this regex"),
+                            )
+                            .on_hover_text(
+                                "Without any check monado-service is ready once it prints something.",
+                            )
+                            .changed();
+                        ui.end_row();
+                    });
+                restart_settings(&mut inst.service_settings, ui, &mut changed);
+                if let Err(err) = inst.service_settings.ready_regex() {
+                    ui.colored_label(Color32::LIGHT_RED, err.to_string());
+                }
+                match inst.service_preview() {
+                    Ok(command_line) => {
+                        ui.monospace(command_line);
+                    }
+                    Err(err) => {
+                        ui.colored_label(Color32::LIGHT_RED, err);
+                    }
+                }
+                if let Some(runtime_dir) = inst.runtime_dir() {
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("XDG_RUNTIME_DIR={}", runtime_dir.display()));
+                        if ui.small_button("Copy").clicked() {
+                            ui.output_mut(|o| {
+                                o.copied_text = format!("XDG_RUNTIME_DIR={}", runtime_dir.display())
+                            });
+                        }
+                    });
+                }
+            });
+        changed
+    }
+}
+
+/// The Service window's command line, kept until the executable, wrapper or arguments change.
+#[derive(Debug)]
+pub struct ServicePreview {
+    settings: (PathBuf, String, String),
+    command_line: Result<String, String>,
+}
+
+impl MonadoInstance {
+    /// `service_argv` as a command line, only searched for on PATH again once the settings change.
+    fn service_preview(&mut self) -> &Result<String, String> {
+        let settings = (
+            self.monado_service(),
+            self.service_settings.wrapper.clone(),
+            self.service_settings.args.clone(),
+        );
+        if self.service_preview.as_ref().map(|p| &p.settings) != Some(&settings) {
+            let command_line = self
+                .service_argv()
+                .map(|argv| shell_words::join(argv.iter().map(|a| a.to_string_lossy())))
+                .map_err(|err| err.to_string());
+            self.service_preview = Some(ServicePreview {
+                settings,
+                command_line,
+            });
+        }
+        &self.service_preview.as_ref().unwrap().command_line
+    }
+}
+
+fn restart_settings(settings: &mut ServiceSettings, ui: &mut egui::Ui, changed: &mut bool) {
+    egui::Grid::new("restart_settings")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Restart");
+            ComboBox::from_id_source("restart_policy")
+                .selected_text(settings.restart.to_string())
+                .show_ui(ui, |ui| {
+                    for policy in RestartPolicy::ALL {
+                        *changed |= ui
+                            .selectable_value(&mut settings.restart, policy, policy.to_string())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            if settings.restart == RestartPolicy::Never {
+                return;
+            }
+            ui.label("Backoff");
+            *changed |= ui
+                .add(
+                    DragValue::new(&mut settings.restart_delay)
+                        .clamp_range(0.0..=MAX_RESTART_DELAY)
+                        .speed(0.1)
+                        .suffix(" s"),
+                )
+                .on_hover_text("Doubled for every restart within the window.")
+                .changed();
+            ui.end_row();
+
+            ui.label("Give Up After");
+            ui.horizontal(|ui| {
+                *changed |= ui
+                    .add(
+                        DragValue::new(&mut settings.max_restarts)
+                            .clamp_range(1..=100)
+                            .suffix(" restarts"),
+                    )
+                    .changed();
+                ui.label("within");
+                *changed |= ui
+                    .add(
+                        DragValue::new(&mut settings.restart_window)
+                            .clamp_range(1.0..=86_400.0)
+                            .speed(1.0)
+                            .suffix(" s"),
+                    )
+                    .changed();
+            });
+            ui.end_row();
+        });
+}
+
+/// Longest wait between two restarts, however often monado-service crashed.
+pub const MAX_RESTART_DELAY: f32 = 60.0;
+
+/// See `ServiceSettings::plan_restart`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPlan {
+    Stay,
+    /// This many restarts already happened within the window.
+    GiveUp(usize),
+    After(Duration),
+}
+
+/// Which exits of monado-service, that rex didn't ask for, start it again.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Non-zero exit codes, signals and crashes.
+    OnFailure,
+    Always,
+}
+impl RestartPolicy {
+    pub const ALL: [RestartPolicy; 3] = [
+        RestartPolicy::Never,
+        RestartPolicy::OnFailure,
+        RestartPolicy::Always,
+    ];
+
+    pub fn restarts_after(self, status: ServiceStatus) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => status.is_abnormal(),
+            RestartPolicy::Always => true,
+        }
+    }
+}
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "Never"),
+            RestartPolicy::OnFailure => write!(f, "On Failure"),
+            RestartPolicy::Always => write!(f, "Always"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ServiceExecutable {
+    /// The instance build once it is installed, otherwise the system's.
+    #[default]
+    Automatic,
+    /// monado-service from PATH.
+    System,
+    InstanceBuild,
+    Custom,
+}
+impl ServiceExecutable {
+    pub const ALL: [ServiceExecutable; 4] = [
+        ServiceExecutable::Automatic,
+        ServiceExecutable::System,
+        ServiceExecutable::InstanceBuild,
+        ServiceExecutable::Custom,
+    ];
+}
+impl fmt::Display for ServiceExecutable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceExecutable::Automatic => write!(f, "Instance Build If Installed"),
+            ServiceExecutable::System => write!(f, "System"),
+            ServiceExecutable::InstanceBuild => write!(f, "Instance Build"),
+            ServiceExecutable::Custom => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Looks a program up like `execvp` would and checks that it can be run.
+pub fn find_executable(program: &Path) -> Result<PathBuf, StartError> {
+    let candidates: Vec<PathBuf> = if program.components().count() > 1 {
+        vec![program.to_path_buf()]
+    } else {
+        std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).map(|dir| dir.join(program)).collect())
+            .unwrap_or_default()
+    };
+    let mut not_executable = None;
+    for candidate in candidates {
+        let Ok(metadata) = std::fs::metadata(&candidate) else {
+            continue;
+        };
+        if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+            return Ok(candidate);
+        }
+        not_executable.get_or_insert(candidate);
+    }
+    Err(match not_executable {
+        Some(path) => StartError::PermissionDenied(path),
+        None => StartError::NotFound(program.to_path_buf()),
+    })
+}
+
+/// Why monado-service couldn't be started.
+#[derive(Debug)]
+pub enum StartError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    /// The arguments, wrapper or working directory can't be used.
+    InvalidSettings(String),
+    SpawnFailed(PopenError),
+    /// The process started, but rex can't follow it.
+    PipeSetupFailed(&'static str),
+    /// The wrapper monado-service is started with, not monado-service itself, couldn't be run.
+    Wrapper(Box<StartError>),
+}
+impl StartError {
+    /// Sorts a failed spawn into the errors the pre-launch check would have reported.
+    pub fn from_popen(program: &Path, err: PopenError) -> Self {
+        match &err {
+            PopenError::IoError(io) if io.kind() == ErrorKind::NotFound => {
+                StartError::NotFound(program.to_path_buf())
+            }
+            PopenError::IoError(io) if io.kind() == ErrorKind::PermissionDenied => {
+                StartError::PermissionDenied(program.to_path_buf())
+            }
+            _ => StartError::SpawnFailed(err),
+        }
+    }
+}
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::NotFound(path) => write!(f, "{} was not found.", path.display()),
+            StartError::PermissionDenied(path) => {
+                write!(f, "{} is not executable.", path.display())
+            }
+            StartError::InvalidSettings(message) => write!(f, "{}", message),
+            StartError::SpawnFailed(err) => write!(f, "Unable to spawn the process: {}", err),
+            StartError::PipeSetupFailed(what) => write!(f, "Unable to follow the process: {}", what),
+            StartError::Wrapper(err) => write!(f, "Wrapper: {}", err),
+        }
+    }
+}
+impl Error for StartError {}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StopSignal {
+    Interrupt,
+    #[default]
+    Terminate,
+}
+impl StopSignal {
+    pub fn signal(self) -> Signal {
+        match self {
+            StopSignal::Interrupt => Signal::SIGINT,
+            StopSignal::Terminate => Signal::SIGTERM,
+        }
+    }
+}
+impl fmt::Display for StopSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.signal())
+    }
+}
+
+/// Where an instance's monado-service is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceStatus {
+    NotStarted,
+    /// Spawned but not ready yet: its IPC socket hasn't appeared or its output hasn't matched
+    /// the readiness pattern, depending on the Service window.
+    Starting,
+    Running,
+    /// Waiting out the backoff before monado-service is started again.
+    Restarting,
+    Stopping,
+    Exited(u32),
+    /// Ended by a signal used to stop processes, e.g. SIGTERM.
+    Killed(u8),
+    /// Ended by any other signal, or in a way that couldn't be determined.
+    Crashed(Option<u8>),
+}
+impl ServiceStatus {
+    pub fn from_exit_status(status: ExitStatus) -> Self {
+        match status {
+            ExitStatus::Exited(code) => ServiceStatus::Exited(code),
+            ExitStatus::Signaled(signal) => match Signal::try_from(signal as i32) {
+                Ok(Signal::SIGINT | Signal::SIGTERM | Signal::SIGKILL | Signal::SIGHUP) => {
+                    ServiceStatus::Killed(signal)
+                }
+                _ => ServiceStatus::Crashed(Some(signal)),
+            },
+            ExitStatus::Other(_) | ExitStatus::Undetermined => ServiceStatus::Crashed(None),
+        }
+    }
+
+    /// Whether the exit is worth pointing out, if rex didn't ask for it.
+    pub fn is_abnormal(self) -> bool {
+        matches!(
+            self,
+            ServiceStatus::Exited(1..) | ServiceStatus::Killed(_) | ServiceStatus::Crashed(_)
+        )
+    }
+
+    pub fn color(self) -> Color32 {
+        match self {
+            ServiceStatus::NotStarted | ServiceStatus::Exited(0) => Color32::GRAY,
+            ServiceStatus::Starting | ServiceStatus::Stopping | ServiceStatus::Restarting => {
+                Color32::YELLOW
+            }
+            ServiceStatus::Running => Color32::GREEN,
+            ServiceStatus::Exited(_) | ServiceStatus::Killed(_) => Color32::from_rgb(255, 140, 0),
+            ServiceStatus::Crashed(_) => Color32::LIGHT_RED,
+        }
+    }
+}
+impl fmt::Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signal_name = |signal: u8| match Signal::try_from(signal as i32) {
+            Ok(signal) => signal.to_string(),
+            Err(_) => format!("signal {}", signal),
+        };
+        match self {
+            ServiceStatus::NotStarted => write!(f, "Not started"),
+            ServiceStatus::Starting => write!(f, "Starting…"),
+            ServiceStatus::Restarting => write!(f, "Restarting…"),
+            ServiceStatus::Running => write!(f, "Running"),
+            ServiceStatus::Stopping => write!(f, "Stopping…"),
+            ServiceStatus::Exited(0) => write!(f, "Exited"),
+            ServiceStatus::Exited(code) => write!(f, "Exited with code {}", code),
+            ServiceStatus::Killed(signal) => write!(f, "Killed by {}", signal_name(*signal)),
+            ServiceStatus::Crashed(Some(signal)) => write!(f, "Crashed ({})", signal_name(*signal)),
+            ServiceStatus::Crashed(None) => write!(f, "Crashed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restarting(restart: RestartPolicy) -> ServiceSettings {
+        ServiceSettings {
+            restart,
+            restart_delay: 1.0,
+            max_restarts: 3,
+            restart_window: 60.0,
+            ..ServiceSettings::default()
+        }
+    }
+
+    #[test]
+    fn backs_off_up_to_the_cap() {
+        let settings = restarting(RestartPolicy::Always);
+        let delays: Vec<f32> = (0..8)
+            .map(|previous| settings.restart_backoff(previous).as_secs_f32())
+            .collect();
+        assert_eq!(delays, [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 60.0, 60.0]);
+        assert_eq!(
+            settings.restart_backoff(usize::MAX),
+            Duration::from_secs_f32(MAX_RESTART_DELAY)
+        );
+        let immediate = ServiceSettings {
+            restart_delay: -1.0,
+            ..settings
+        };
+        assert_eq!(immediate.restart_backoff(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn gives_up_after_too_many_restarts_in_the_window() {
+        let settings = restarting(RestartPolicy::OnFailure);
+        let now = Instant::now() + Duration::from_secs(1000);
+        let ago = |secs| now - Duration::from_secs(secs);
+        let crashed = ServiceStatus::Crashed(Some(11));
+
+        assert_eq!(
+            settings.plan_restart(ServiceStatus::Exited(0), &[], now),
+            RestartPlan::Stay
+        );
+        assert_eq!(
+            settings.plan_restart(crashed, &[], now),
+            RestartPlan::After(Duration::from_secs(1))
+        );
+        assert_eq!(
+            settings.plan_restart(crashed, &[ago(50), ago(10)], now),
+            RestartPlan::After(Duration::from_secs(4))
+        );
+        assert_eq!(
+            settings.plan_restart(crashed, &[ago(50), ago(20), ago(10)], now),
+            RestartPlan::GiveUp(3)
+        );
+        // Restarts that left the window don't count.
+        assert_eq!(
+            settings.plan_restart(crashed, &[ago(90), ago(60), ago(10)], now),
+            RestartPlan::After(Duration::from_secs(2))
+        );
+        assert_eq!(
+            restarting(RestartPolicy::Never).plan_restart(crashed, &[], now),
+            RestartPlan::Stay
+        );
+    }
+
+    #[test]
+    fn blames_the_wrapper() {
+        let dir = std::env::temp_dir().join(format!("rex-wrapper-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut instance = MonadoInstance::create_load(&dir, "wrapped".to_string()).unwrap();
+        instance.service_settings.executable = ServiceExecutable::Custom;
+        instance.service_settings.custom_executable = "/bin/true".to_string();
+        instance.service_settings.wrapper = "rex-no-such-wrapper --flag".to_string();
+
+        let err = instance.service_argv().unwrap_err();
+        assert!(
+            matches!(&err, StartError::Wrapper(inner) if matches!(**inner, StartError::NotFound(_)))
+        );
+        assert_eq!(
+            err.to_string(),
+            "Wrapper: rex-no-such-wrapper was not found."
+        );
+
+        instance.service_settings.wrapper = "env".to_string();
+        let argv = instance.service_argv().unwrap();
+        assert!(argv[0].to_string_lossy().ends_with("/env"));
+        assert_eq!(argv[1], "/bin/true");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{ansi, console::ConsoleView, instance::MonadoInstance};
+use egui::Context;
+use native_dialog::MessageDialog;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const PLAIN_EXTENSION: &str = ".log";
+const RAW_EXTENSION: &str = ".ansi.log";
+
+/// One monado-service run written to disk, once as printed and once without ANSI escapes.
+pub struct SessionLog {
+    raw: LineWriter<File>,
+    plain: LineWriter<File>,
+}
+impl SessionLog {
+    /// Starts a new session in `dir` named after the current local time, beginning with `header`.
+    pub fn create(dir: &Path, header: &str) -> io::Result<SessionLog> {
+        fs::create_dir_all(dir)?;
+        let timestamp = timestamp();
+        // Restarts can happen within the same second.
+        let mut name = timestamp.clone();
+        let mut n = 1;
+        while dir.join(format!("{}{}", name, PLAIN_EXTENSION)).exists() {
+            n += 1;
+            name = format!("{}-{}", timestamp, n);
+        }
+        let open = |extension: &str| {
+            OpenOptions::new()
+                .append(true)
+                .create_new(true)
+                .open(dir.join(format!("{}{}", name, extension)))
+                .map(LineWriter::new)
+        };
+        let mut log = SessionLog {
+            plain: open(PLAIN_EXTENSION)?,
+            raw: open(RAW_EXTENSION)?,
+        };
+        for line in header.lines() {
+            log.write_line(&format!("# {}", line));
+        }
+        log.write_line("");
+        Ok(log)
+    }
+
+    /// Another handle to the same files, lines from both end up in order they were written.
+    pub fn try_clone(&self) -> io::Result<SessionLog> {
+        Ok(SessionLog {
+            raw: LineWriter::new(self.raw.get_ref().try_clone()?),
+            plain: LineWriter::new(self.plain.get_ref().try_clone()?),
+        })
+    }
+
+    /// Writes a line of output, a failing disk shouldn't stop the console so errors are ignored.
+    pub fn write_line(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let _ = writeln!(self.raw, "{}", line);
+        let _ = writeln!(self.plain, "{}", ansi::strip(line));
+    }
+}
+impl std::fmt::Debug for SessionLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionLog").finish_non_exhaustive()
+    }
+}
+
+/// Local time as `YYYY-MM-DD_HH-MM-SS`, sortable and safe in file names.
+fn timestamp() -> String {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// A past session in an instance's log directory.
+#[derive(Debug, Clone)]
+pub struct SessionLogEntry {
+    pub name: String,
+    pub plain: PathBuf,
+    pub raw: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// Every session in `dir`, newest first.
+pub fn list(dir: &Path) -> Vec<SessionLogEntry> {
+    let mut entries: Vec<SessionLogEntry> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_name = entry.file_name().to_str()?.to_string();
+            if file_name.ends_with(RAW_EXTENSION) {
+                return None;
+            }
+            let name = file_name.strip_suffix(PLAIN_EXTENSION)?.to_string();
+            let metadata = entry.metadata().ok()?;
+            Some(SessionLogEntry {
+                raw: dir.join(format!("{}{}", name, RAW_EXTENSION)),
+                plain: entry.path(),
+                name,
+                modified: metadata.modified().ok()?,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.modified));
+    entries
+}
+
+/// Deletes all but the newest `keep` sessions.
+pub fn prune(dir: &Path, keep: usize) {
+    for entry in list(dir).into_iter().skip(keep) {
+        remove(&entry);
+    }
+}
+
+fn remove(entry: &SessionLogEntry) {
+    for path in [&entry.plain, &entry.raw] {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!("Unable to remove session log {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+impl MonadoInstance {
+    /// Loads a past session into a console of its own and shows it.
+    pub fn open_session_log(&mut self, entry: &SessionLogEntry) -> io::Result<()> {
+        // The raw variant keeps the colors, older sessions might only have the plain one.
+        let text = fs::read_to_string(&entry.raw).or_else(|_| fs::read_to_string(&entry.plain))?;
+        let mut console = self.console.sibling();
+        console.buffer.push_str(&text);
+        if !text.ends_with('\n') {
+            console.buffer.push_str("\n");
+        }
+        self.opened_log = Some((entry.name.clone(), console));
+        self.shown_console = ConsoleView::SessionLog;
+        Ok(())
+    }
+}
+
+pub fn update(inst: &mut MonadoInstance, ctx: &Context) {
+    egui::Window::new("Session Logs")
+        .default_open(false)
+        .collapsible(true)
+        .show(ctx, |ui| {
+            let entries = list(&inst.logs_dir());
+            if entries.is_empty() {
+                ui.label("No sessions yet, every monado-service run is saved here.");
+                return;
+            }
+            let mut open = None;
+            let mut delete = None;
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("session_logs")
+                        .num_columns(3)
+                        .show(ui, |ui| {
+                            for entry in &entries {
+                                ui.label(&entry.name);
+                                ui.label(format!("{:.1} KiB", entry.size as f32 / 1024.0));
+                                ui.horizontal(|ui| {
+                                    if ui.small_button("Open").clicked() {
+                                        open = Some(entry.clone());
+                                    }
+                                    if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                                        delete = Some(entry.clone());
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+            if let Some(entry) = delete {
+                remove(&entry);
+            }
+            if let Some(entry) = open {
+                if let Err(err) = inst.open_session_log(&entry) {
+                    println!("Error opening session log: {}", err);
+
+                    MessageDialog::new()
+                        .set_title("Session Log Error")
+                        .set_text(&format!(
+                            "Unable to open session log '{}'.\nError:\n{}",
+                            entry.name, err
+                        ))
+                        .set_type(native_dialog::MessageType::Error)
+                        .show_alert()
+                        .expect("Error creating dialog window for session log error");
+                }
+            }
+            ui.label(format!("Saved in {}", inst.logs_dir().display()));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_raw_and_plain_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("rex-session-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for i in 0..3 {
+            let mut log = SessionLog::create(&dir, "command: monado-service").unwrap();
+            log.try_clone()
+                .unwrap()
+                .write_line(&format!("\x1b[31mrun {}\x1b[0m\n", i));
+            log.write_line("monado-service: Exited.");
+        }
+        let entries = list(&dir);
+        assert_eq!(entries.len(), 3);
+        // Started within the same second, the names still have to differ.
+        let mut names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        names.dedup();
+        assert_eq!(names.len(), 3);
+
+        let entry = entries
+            .iter()
+            .find(|e| fs::read_to_string(&e.plain).unwrap().contains("run 2"))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&entry.plain).unwrap(),
+            "# command: monado-service\n\nrun 2\nmonado-service: Exited.\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&entry.raw).unwrap(),
+            "# command: monado-service\n\n\x1b[31mrun 2\x1b[0m\nmonado-service: Exited.\n"
+        );
+
+        prune(&dir, 1);
+        assert_eq!(list(&dir).len(), 1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
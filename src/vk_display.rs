@@ -0,0 +1,337 @@
+use crate::{
+    ansi,
+    child_output::forward_lines,
+    compositor::{WindowMode, WindowType},
+    instance::MonadoInstance,
+    log_record::LogRecord,
+    service::find_executable,
+};
+use egui::{Color32, ComboBox, Context, DragValue, Ui};
+use regex::Regex;
+use std::{
+    fmt,
+    fs::DirBuilder,
+    os::unix::fs::DirBuilderExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use subprocess::{Exec, Popen, Redirection};
+
+/// No machine has this many displays, asking for it makes monado-service list the ones it found.
+const PROBE_INDEX: u32 = 999;
+/// monado-service might carry on with another target after listing, it is stopped after this.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A display Vulkan can drive directly, numbered like `XRT_COMPOSITOR_FORCE_VK_DISPLAY` expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VkDisplay {
+    pub index: u32,
+    pub name: String,
+    pub resolution: Option<(u32, u32)>,
+}
+impl fmt::Display for VkDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.index, self.name)?;
+        if let Some((width, height)) = self.resolution {
+            write!(f, " ({}x{})", width, height)?;
+        }
+        Ok(())
+    }
+}
+
+fn display_regex() -> Regex {
+    Regex::new(r"^\[(\d+)\]\s+(.+?)(?:\s+with resolution\s+(\d+)x(\d+).*)?$").unwrap()
+}
+
+/// Picks `[0] Valve Index with resolution 2880x1600, ...` style lines out of monado-service's
+/// output, with or without log prefixes and colors.
+pub fn parse_displays(output: &str) -> Vec<VkDisplay> {
+    let regex = display_regex();
+    let mut displays: Vec<VkDisplay> = Vec::new();
+    for line in output.lines() {
+        let line = ansi::strip(line);
+        let message = match LogRecord::parse(&line) {
+            Some(record) => record.message,
+            None => line.trim().to_string(),
+        };
+        let Some(captures) = regex.captures(message.trim()) else {
+            continue;
+        };
+        let Ok(index) = captures[1].parse() else {
+            continue;
+        };
+        let resolution = match (captures.get(3), captures.get(4)) {
+            (Some(width), Some(height)) => width
+                .as_str()
+                .parse()
+                .ok()
+                .zip(height.as_str().parse().ok()),
+            _ => None,
+        };
+        // A repeated listing replaces the earlier one.
+        displays.retain(|d| d.index != index);
+        displays.push(VkDisplay {
+            index,
+            name: captures[2].to_string(),
+            resolution,
+        });
+    }
+    displays.sort_by_key(|d| d.index);
+    displays
+}
+
+/// What is known about an instance's Vulkan displays.
+#[derive(Debug, Default)]
+pub enum VkDisplayProbe {
+    #[default]
+    NotRun,
+    Running(Receiver<Result<Vec<VkDisplay>, String>>),
+    Done(Result<Vec<VkDisplay>, String>),
+}
+impl VkDisplayProbe {
+    /// Picks up the result of a finished probe run.
+    fn poll(&mut self) {
+        if let VkDisplayProbe::Running(receiver) = self {
+            match receiver.try_recv() {
+                Ok(result) => *self = VkDisplayProbe::Done(result),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    *self = VkDisplayProbe::Done(Err("The probe stopped unexpectedly.".into()))
+                }
+            }
+        }
+    }
+
+    fn displays(&self) -> &[VkDisplay] {
+        match self {
+            VkDisplayProbe::Done(Ok(displays)) => displays,
+            _ => &[],
+        }
+    }
+}
+
+impl MonadoInstance {
+    /// A runtime dir of its own, the probe must not take over the IPC socket of a running service.
+    fn probe_runtime_dir(&self) -> PathBuf {
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("rex")
+            .join(format!("{}.vk-probe", self.name()))
+    }
+
+    /// monado-service without wrapper or arguments, asked for a display that doesn't exist.
+    fn probe_command(&self) -> Result<Exec, String> {
+        let program = find_executable(&self.monado_service()).map_err(|err| err.to_string())?;
+        let runtime_dir = self.probe_runtime_dir();
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&runtime_dir)
+            .map_err(|err| format!("Unable to create {}: {}", runtime_dir.display(), err))?;
+        let mut command = self
+            .env_vars
+            .set_custom_vars(Exec::cmd(program).env("XDG_RUNTIME_DIR", &runtime_dir));
+        command = WindowType {
+            mode: WindowMode::Vk,
+            vk_display: PROBE_INDEX,
+            ..WindowType::default()
+        }
+        .set_vars(command);
+        Ok(command
+            .stderr(Redirection::Merge)
+            .stdout(Redirection::Pipe)
+            .stdin(Redirection::None))
+    }
+
+    /// Runs monado-service in the background to find out which displays it can use.
+    pub fn probe_vk_displays(&mut self, ctx: Context) {
+        let child = self
+            .probe_command()
+            .and_then(|command| command.popen().map_err(|err| err.to_string()));
+        let child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                self.vk_displays = VkDisplayProbe::Done(Err(err));
+                return;
+            }
+        };
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(run_probe(child));
+            ctx.request_repaint();
+        });
+        self.vk_displays = VkDisplayProbe::Running(receiver);
+    }
+}
+
+fn run_probe(mut child: Popen) -> Result<Vec<VkDisplay>, String> {
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or("monado-service has no readable output.")?;
+    let listed = Arc::new(AtomicBool::new(false));
+    let output = Arc::new(Mutex::new(String::new()));
+    let reader = {
+        let listed = listed.clone();
+        let output = output.clone();
+        let regex = display_regex();
+        thread::spawn(move || {
+            let mut in_list = false;
+            forward_lines(stdout, |line| {
+                let plain = ansi::strip(&line);
+                let message = LogRecord::parse(&plain).map_or(plain.clone(), |r| r.message);
+                let is_display = regex.is_match(message.trim());
+                // The first line after the list means it is complete.
+                if in_list && !is_display {
+                    listed.store(true, Ordering::Relaxed);
+                }
+                in_list |= is_display;
+                output.lock().unwrap().push_str(&line);
+                true
+            });
+        })
+    };
+
+    let started = Instant::now();
+    while child.poll().is_none()
+        && !listed.load(Ordering::Relaxed)
+        && started.elapsed() < PROBE_TIMEOUT
+    {
+        thread::sleep(Duration::from_millis(100));
+    }
+    if child.poll().is_none() {
+        let _ = child.terminate();
+        if !matches!(child.wait_timeout(Duration::from_secs(2)), Ok(Some(_))) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+    // Something monado-service started can keep the pipe open, don't wait for that.
+    let stopped = Instant::now();
+    while !reader.is_finished() && stopped.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let output = output.lock().unwrap().clone();
+
+    let displays = parse_displays(&output);
+    if displays.is_empty() {
+        let last = output
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("");
+        return Err(format!(
+            "monado-service listed no Vulkan displays. Last output:\n{}",
+            ansi::strip(last).trim()
+        ));
+    }
+    Ok(displays)
+}
+
+/// The Vulkan Display mode of the Forcing panel. Sets `probe` when the user asks for a probe run,
+/// which needs the whole instance.
+pub fn picker(
+    window_type: &mut WindowType,
+    vk_displays: &mut VkDisplayProbe,
+    probe: &mut bool,
+    ui: &mut Ui,
+) -> bool {
+    vk_displays.poll();
+    let mut changed = false;
+    ui.horizontal_wrapped(|ui| {
+        changed |= ui
+            .radio_value(&mut window_type.mode, WindowMode::Vk, "Vulkan Display")
+            .on_hover_text(
+                "Use a display through VK_KHR_display, like direct mode without a desktop.",
+            )
+            .changed();
+        ui.add_enabled_ui(window_type.mode == WindowMode::Vk, |ui| {
+            let displays = vk_displays.displays();
+            if !displays.is_empty() {
+                let selected = displays
+                    .iter()
+                    .find(|d| d.index == window_type.vk_display)
+                    .map_or_else(
+                        || format!("{}: Not found", window_type.vk_display),
+                        |d| d.to_string(),
+                    );
+                ComboBox::from_id_source("vk_display")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for display in displays {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut window_type.vk_display,
+                                    display.index,
+                                    display.to_string(),
+                                )
+                                .changed();
+                        }
+                    });
+            }
+            ui.label("Index");
+            changed |= ui
+                .add(DragValue::new(&mut window_type.vk_display).clamp_range(0..=PROBE_INDEX - 1))
+                .changed();
+            if let VkDisplayProbe::Running(_) = vk_displays {
+                ui.spinner();
+            } else if ui
+                .button("Probe")
+                .on_hover_text("Run monado-service once to list the displays it can use.")
+                .clicked()
+            {
+                *probe = true;
+            }
+        });
+    });
+    if let VkDisplayProbe::Done(Err(err)) = vk_displays {
+        ui.colored_label(Color32::LIGHT_RED, err.as_str());
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_monado_display_list() {
+        let output = "\x1b[32m INFO\x1b[0m [comp_main] Doing init\n\
+            \x1b[1;31mERROR\x1b[0m [comp_window_vk_display] Requested display 999, but only 2 displays are available.\n\
+            \x1b[1;31mERROR\x1b[0m [print_found_displays] == Found Displays ==\n\
+            \x1b[1;31mERROR\x1b[0m [print_found_displays] [0] Valve Corporation Index HMD with resolution 2880x1600, dims 0x0\n\
+            \x1b[1;31mERROR\x1b[0m [print_found_displays] [1] DP-2 with resolution 2560x1440, dims 597x336\n\
+            [2] Some Display\n\
+            \x1b[1;31mERROR\x1b[0m [comp_main] Failed to init any compositor target\n";
+        assert_eq!(
+            parse_displays(output),
+            vec![
+                VkDisplay {
+                    index: 0,
+                    name: "Valve Corporation Index HMD".to_string(),
+                    resolution: Some((2880, 1600)),
+                },
+                VkDisplay {
+                    index: 1,
+                    name: "DP-2".to_string(),
+                    resolution: Some((2560, 1440)),
+                },
+                VkDisplay {
+                    index: 2,
+                    name: "Some Display".to_string(),
+                    resolution: None,
+                },
+            ]
+        );
+        assert_eq!(
+            parse_displays("\x1b[32m INFO\x1b[0m [comp_main] Doing init\n"),
+            vec![]
+        );
+    }
+}